@@ -0,0 +1,42 @@
+// Times recursive calls to a small and a large function on both engines.
+// Run with `cargo bench`; calling a function should not get slower as its body grows.
+
+use std::time::Instant;
+use gaggiscript::{Interpreter, Engine};
+
+const FIB: &str = "
+let fib = fn(n) { if (n < 2) { n } else { fib(n - 1) + fib(n - 2) } };
+fib(22)
+";
+
+// same recursion depth and call count as FIB, with a few hundred statements
+// that are parsed into the body but never run
+fn large_fib() -> String {
+    let padding: String = (0..300)
+        .map(|i| format!("let unused = [{}, \"padding\", {{\"k\": {}}}];", i, i))
+        .collect();
+
+    format!("
+let fib = fn(n) {{ if (n < 2) {{ return n; }}; return fib(n - 1) + fib(n - 2); {} }};
+fib(22)
+", padding)
+}
+
+fn bench(name: &str, engine: Engine, code: &str) {
+    let mut interpreter = Interpreter::with_engine(engine);
+    let program = interpreter.parse(code).unwrap_or_else(|_| panic!("{} does not parse", name));
+
+    let start = Instant::now();
+    let result = interpreter.run(program).unwrap_or_else(|_| panic!("{} failed", name));
+
+    println!("{:<24} {:?}: {} in {:?}", name, engine, result, start.elapsed());
+}
+
+fn main() {
+    let large = large_fib();
+
+    for engine in [Engine::Evaluator, Engine::Vm].iter() {
+        bench("fib(22)", *engine, FIB);
+        bench("fib(22), large body", *engine, &large);
+    }
+}
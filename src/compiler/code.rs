@@ -0,0 +1,179 @@
+use crate::parser::Infix;
+
+pub type Instructions = Vec<u8>;
+
+#[repr(u8)]
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum Opcode {
+    Constant,
+    Pop,
+    True,
+    False,
+    Null,
+    Infix,
+    Minus,
+    Bang,
+    BitNot,
+    Jump,
+    JumpNotTruthy,
+    GetGlobal,
+    SetGlobal,
+    AssignGlobal,
+    GetLocal,
+    SetLocal,
+    DefineLocal,
+    GetFree,
+    SetFree,
+    Update,
+    Array,
+    Hash,
+    HashInsert,
+    Index,
+    Call,
+    ReturnValue,
+    Closure,
+    Range,
+    Iter,
+    IterNext,
+    Raise
+}
+
+// Every opcode, indexed by its byte value
+const OPCODES: &[Opcode] = &[
+    Opcode::Constant,
+    Opcode::Pop,
+    Opcode::True,
+    Opcode::False,
+    Opcode::Null,
+    Opcode::Infix,
+    Opcode::Minus,
+    Opcode::Bang,
+    Opcode::BitNot,
+    Opcode::Jump,
+    Opcode::JumpNotTruthy,
+    Opcode::GetGlobal,
+    Opcode::SetGlobal,
+    Opcode::AssignGlobal,
+    Opcode::GetLocal,
+    Opcode::SetLocal,
+    Opcode::DefineLocal,
+    Opcode::GetFree,
+    Opcode::SetFree,
+    Opcode::Update,
+    Opcode::Array,
+    Opcode::Hash,
+    Opcode::HashInsert,
+    Opcode::Index,
+    Opcode::Call,
+    Opcode::ReturnValue,
+    Opcode::Closure,
+    Opcode::Range,
+    Opcode::Iter,
+    Opcode::IterNext,
+    Opcode::Raise
+];
+
+// Infix operators, indexed by the operand of Opcode::Infix
+pub const INFIXES: &[Infix] = &[
+    Infix::Plus,
+    Infix::Minus,
+    Infix::Multiply,
+    Infix::Divide,
+    Infix::Equal,
+    Infix::NotEqual,
+    Infix::GreaterThan,
+    Infix::LessThan,
+    Infix::GreaterEqual,
+    Infix::LessEqual,
+    Infix::Modulo,
+    Infix::Power,
+    Infix::BitAnd,
+    Infix::BitOr,
+    Infix::BitXor,
+    Infix::ShiftLeft,
+    Infix::ShiftRight
+];
+
+// Operand of Opcode::Update for a plain `=`
+pub const NO_INFIX: usize = u8::MAX as usize;
+
+impl Opcode {
+
+    pub fn from_byte(byte: u8) -> Opcode {
+        OPCODES[byte as usize]
+    }
+
+    // Width in bytes of each operand. Closure is also followed by
+    // (is_local, index) byte pairs, one per captured variable
+    pub fn operand_widths(self) -> &'static [usize] {
+        match self {
+            Opcode::Constant      => &[4],
+            Opcode::Infix         => &[1],
+            Opcode::Jump          => &[4],
+            Opcode::JumpNotTruthy => &[4],
+            Opcode::GetGlobal     => &[2],
+            Opcode::SetGlobal     => &[2],
+            Opcode::AssignGlobal  => &[2],
+            Opcode::GetLocal      => &[1],
+            Opcode::SetLocal      => &[1],
+            Opcode::DefineLocal   => &[1],
+            Opcode::GetFree       => &[1],
+            Opcode::SetFree       => &[1],
+            Opcode::Update        => &[1, 1],
+            Opcode::Array         => &[2],
+            Opcode::Call          => &[1],
+            Opcode::Closure       => &[4, 1],
+            Opcode::Range         => &[1],
+            Opcode::IterNext      => &[1, 4],
+            Opcode::Raise         => &[4],
+            _                     => &[]
+        }
+    }
+
+    // Change in the height of the stack when the instruction runs on, so
+    // IterNext counts the values it pushes for another iteration
+    pub fn stack_effect(self, operands: &[usize]) -> isize {
+        let operand = |i: usize| operands.get(i).copied().unwrap_or(0) as isize;
+
+        match self {
+            Opcode::Constant | Opcode::True | Opcode::False | Opcode::Null => 1,
+            Opcode::GetGlobal | Opcode::GetLocal | Opcode::GetFree        => 1,
+            Opcode::Hash | Opcode::Closure | Opcode::Iter                 => 1,
+            Opcode::Minus | Opcode::Bang | Opcode::BitNot | Opcode::Jump  => 0,
+            Opcode::Raise                                                 => 0,
+            Opcode::Pop | Opcode::Infix | Opcode::JumpNotTruthy           => -1,
+            Opcode::SetGlobal | Opcode::AssignGlobal | Opcode::SetLocal   => -1,
+            Opcode::DefineLocal | Opcode::SetFree | Opcode::Index         => -1,
+            Opcode::Range | Opcode::ReturnValue                           => -1,
+            Opcode::HashInsert                                            => -2,
+            Opcode::Update                                                => -operand(0),
+            Opcode::Array                                                 => 1 - operand(0),
+            Opcode::Call                                                  => -operand(0),
+            Opcode::IterNext                                              => 1 + operand(0)
+        }
+    }
+
+}
+
+pub fn make(op: Opcode, operands: &[usize]) -> Instructions {
+    let mut instruction = vec!(op as u8);
+
+    for (width, operand) in op.operand_widths().iter().zip(operands) {
+        let bytes = (*operand as u32).to_be_bytes();
+        instruction.extend_from_slice(&bytes[4 - width..]);
+    }
+
+    instruction
+}
+
+pub fn read_u8(ins: &[u8], ip: usize) -> usize {
+    ins[ip] as usize
+}
+
+pub fn read_u16(ins: &[u8], ip: usize) -> usize {
+    u16::from_be_bytes([ins[ip], ins[ip + 1]]) as usize
+}
+
+pub fn read_u32(ins: &[u8], ip: usize) -> usize {
+    u32::from_be_bytes([ins[ip], ins[ip + 1], ins[ip + 2], ins[ip + 3]]) as usize
+}
@@ -0,0 +1,583 @@
+pub mod code;
+pub mod symbol_table;
+
+use code::{Opcode, Instructions, INFIXES, NO_INFIX, make};
+use symbol_table::{SymbolTable, Symbol, Scope};
+use crate::parser::{Program, Statement, Expression, Prefix, Infix};
+use crate::evaluator::object::Object;
+use crate::evaluator::builtins;
+use crate::evaluator::assignment_path;
+use crate::error::{Error, Span};
+use crate::evaluator::bigint::BigInt;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+// Limits imposed by the operand widths in code::Opcode::operand_widths
+const MAX_U8:  usize = u8::MAX as usize;
+const MAX_U16: usize = u16::MAX as usize;
+const MAX_U32: usize = u32::MAX as usize;
+
+#[derive(PartialEq)]
+pub struct CompiledFunction {
+    pub instructions: Instructions,
+    pub num_locals:   usize,
+    pub num_params:   usize,
+    // start offset of the instructions that can fail, with their source location
+    pub spans:        Vec<(usize, Span)>
+}
+
+impl CompiledFunction {
+
+    pub fn span_at(&self, ip: usize) -> Span {
+        match self.spans.binary_search_by_key(&ip, |i| i.0) {
+            Ok(i)  => self.spans[i].1,
+            Err(_) => Span::default()
+        }
+    }
+
+}
+
+pub enum Constant {
+    Object(Object),
+    Function(Rc<CompiledFunction>)
+}
+
+// Identity of a constant object, so each value is stored once however many
+// times and runs it appears in
+#[derive(PartialEq, Eq, Hash)]
+enum ConstantKey {
+    Integer(i64),
+    BigInteger(BigInt),
+    // the bits, since floats have no Eq
+    Float(u64),
+    String(String),
+    Builtin(&'static str)
+}
+
+fn constant_key(obj: &Object) -> Option<ConstantKey> {
+    Some(match obj {
+        Object::Integer(i)    => ConstantKey::Integer(*i),
+        Object::BigInteger(i) => ConstantKey::BigInteger(i.clone()),
+        Object::Float(i)      => ConstantKey::Float(i.to_bits()),
+        Object::String(i)     => ConstantKey::String(i.clone()),
+        Object::Builtin(i)    => ConstantKey::Builtin(i.name),
+        _                     => return None
+    })
+}
+
+#[derive(Default)]
+struct CompilationScope {
+    instructions: Instructions,
+    spans:        Vec<(usize, Span)>,
+    // height of the stack after the instructions emitted so far
+    depth:        isize,
+    // enclosing loops, innermost last
+    loops:        Vec<Loop>
+}
+
+struct Loop {
+    start:  usize,
+    // height of the stack at `start`, which break and continue pop back down to
+    depth:  isize,
+    // break jumps to patch once the end of the loop is known
+    breaks: Vec<usize>
+}
+
+pub struct Compiler {
+    constants: Vec<Constant>,
+    // index of every constant object, see ConstantKey
+    interned:  HashMap<ConstantKey, usize>,
+    symbols:   SymbolTable,
+    scopes:    Vec<CompilationScope>
+}
+
+// Keeps compiling against the globals and constants of a previous run, as the REPL does
+pub fn new_with_state(symbols: SymbolTable, constants: Vec<Constant>) -> Compiler {
+    let interned = constants.iter().enumerate()
+        .filter_map(|(n, i)| match i {
+            Constant::Object(i)   => Some((constant_key(i)?, n)),
+            Constant::Function(_) => None
+        })
+        .collect();
+
+    Compiler{
+        constants,
+        interned,
+        symbols,
+        scopes: vec!(CompilationScope::default())
+    }
+}
+
+fn err(err: &str, span: Span) -> Error {
+    Error::Compile(String::from(err), span)
+}
+
+impl Compiler {
+
+    // Compiles the program into the body of a function taking no arguments
+    // that returns the value of the last statement, like evaluator::run_program
+    pub fn compile(&mut self, program: &Program) -> Result<Rc<CompiledFunction>, Error> {
+        self.compile_block(&program.statements)?;
+        self.emit(Opcode::ReturnValue, &[]);
+
+        let scope = self.scopes.pop().unwrap_or_default();
+        self.scopes.push(CompilationScope::default());
+
+        Ok(Rc::new(CompiledFunction{
+            instructions: scope.instructions,
+            // locals of the loops at the top level
+            num_locals:   check(std::mem::take(&mut self.symbols.num_locals), MAX_U8 + 1, "too many local variables")?,
+            num_params:   0,
+            spans:        scope.spans
+        }))
+    }
+
+    // Leaves the value of the block on the stack: its last expression, or null
+    fn compile_block(&mut self, stmts: &[Statement]) -> Result<(), Error> {
+        match stmts.split_last() {
+            Some((Statement::ExpressionStatement(last), rest)) => {
+                for stmt in rest {
+                    self.compile_statement(stmt)?;
+                }
+                self.compile_expression(last)
+            },
+            _ => {
+                for stmt in stmts {
+                    self.compile_statement(stmt)?;
+                }
+                self.emit(Opcode::Null, &[]);
+                Ok(())
+            }
+        }
+    }
+
+    fn compile_statement(&mut self, stmt: &Statement) -> Result<(), Error> {
+        match stmt {
+            Statement::ExpressionStatement(i) => {
+                self.compile_expression(i)?;
+                self.emit(Opcode::Pop, &[]);
+            },
+            Statement::ReturnStatement(i)     => {
+                self.compile_expression(i)?;
+                self.emit(Opcode::ReturnValue, &[]);
+            },
+            Statement::LetStatement(i, a, _)  => {
+                // a first `let` in a loop body gets a new binding on every iteration
+                let fresh = self.symbols.is_new_in_block(i);
+
+                // bound first so that the function can call itself
+                if let Expression::FunctionLiteral(_, _) = a {
+                    let symbol = self.symbols.define(i);
+                    if fresh {
+                        self.emit(Opcode::Null, &[]);
+                        self.emit_define(symbol)?;
+                    }
+                    self.compile_expression(a)?;
+                    self.emit_set(symbol)?;
+                } else {
+                    self.compile_expression(a)?;
+                    let symbol = self.symbols.define(i);
+
+                    if fresh {self.emit_define(symbol)?} else {self.emit_set(symbol)?}
+                }
+            },
+            Statement::ForStatement(i, e, a, s) => self.compile_for(i, e, a, *s)?,
+            Statement::WhileStatement(i, a, s) => self.compile_while(i, a, *s)?,
+            Statement::BreakStatement         => {
+                let depth = self.unwind_loop();
+                let jump = self.emit(Opcode::Jump, &[0]);
+                self.current().loops.last_mut().unwrap().breaks.push(jump);
+                self.current().depth = depth;
+            },
+            Statement::ContinueStatement      => {
+                let depth = self.unwind_loop();
+                let start = self.current().loops.last().unwrap().start;
+                self.emit(Opcode::Jump, &[start]);
+                self.current().depth = depth;
+            }
+        }
+        Ok(())
+    }
+
+    // Pops what the expressions around a break or continue left on the stack,
+    // returning the height to carry on with after the jump
+    fn unwind_loop(&mut self) -> isize {
+        let depth = self.current().depth;
+        let target = self.current().loops.last().unwrap().depth;
+
+        for _ in target..depth {
+            self.emit(Opcode::Pop, &[]);
+        }
+        depth
+    }
+
+    // Keeps the iterable and the next index on the stack while the body runs,
+    // the loop variables are locals of a block scope around the body
+    fn compile_for(&mut self, names: &[String], iterable: &Expression, body: &[Statement], span: Span) -> Result<(), Error> {
+        self.compile_expression(iterable)?;
+        self.emit_spanned(Opcode::Iter, &[], span);
+
+        let start = self.current().instructions.len();
+        let depth = self.current().depth;
+        let next = self.emit(Opcode::IterNext, &[(names.len() == 2) as usize, 0]);
+
+        self.symbols.enter_block();
+        self.current().loops.push(Loop{start, depth, breaks: Vec::new()});
+        let compiled = self.compile_for_body(names, body);
+        let finished = self.current().loops.pop().unwrap();
+        self.symbols.leave_block();
+        compiled?;
+
+        self.emit(Opcode::Pop, &[]);
+        self.emit(Opcode::Jump, &[start]);
+
+        self.patch_jump(next);
+        for jump in finished.breaks {
+            self.patch_jump(jump);
+        }
+        self.emit(Opcode::Pop, &[]);
+        self.emit(Opcode::Pop, &[]);
+        Ok(())
+    }
+
+    fn compile_for_body(&mut self, names: &[String], body: &[Statement]) -> Result<(), Error> {
+        // IterNext pushes the values in order, so the last name is bound first
+        for name in names.iter().rev() {
+            let symbol = self.symbols.define(name);
+            self.emit_define(symbol)?;
+        }
+
+        self.compile_block(body)
+    }
+
+    fn compile_while(&mut self, condition: &Expression, body: &[Statement], span: Span) -> Result<(), Error> {
+        let start = self.current().instructions.len();
+        let depth = self.current().depth;
+
+        self.compile_expression(condition)?;
+        let jump_not_truthy = self.emit_spanned(Opcode::JumpNotTruthy, &[0], span);
+
+        self.current().loops.push(Loop{start, depth, breaks: Vec::new()});
+        let compiled = self.compile_block(body);
+        let finished = self.current().loops.pop().unwrap();
+        compiled?;
+
+        self.emit(Opcode::Pop, &[]);
+        self.emit(Opcode::Jump, &[start]);
+
+        self.patch_jump(jump_not_truthy);
+        for jump in finished.breaks {
+            self.patch_jump(jump);
+        }
+        Ok(())
+    }
+
+    fn compile_expression(&mut self, exp: &Expression) -> Result<(), Error> {
+        match exp {
+            Expression::Int(i)                      => self.emit_constant(Object::Integer(*i))?,
+            Expression::BigInt(i)                   => self.emit_constant(Object::BigInteger(i.clone()))?,
+            Expression::Float(i)                    => self.emit_constant(Object::Float(*i))?,
+            Expression::Str(i)                      => self.emit_constant(Object::String(i.clone()))?,
+            Expression::Bool(true)                  => {self.emit(Opcode::True, &[]);},
+            Expression::Bool(false)                 => {self.emit(Opcode::False, &[]);},
+            Expression::Ident(i, s)                 => self.compile_identifier(i, *s)?,
+            Expression::ArrayLiteral(i)             => {
+                for exp in i {
+                    self.compile_expression(exp)?;
+                }
+                self.emit(Opcode::Array, &[check(i.len(), MAX_U16, "too many array elements")?]);
+            },
+            Expression::HashLiteral(i)              => {
+                self.emit(Opcode::Hash, &[]);
+                for (key, value, span) in i {
+                    self.compile_expression(key)?;
+                    self.compile_expression(value)?;
+                    self.emit_spanned(Opcode::HashInsert, &[], *span);
+                }
+            },
+            Expression::IndexExpression(i, e, s)    => {
+                self.compile_expression(i)?;
+                self.compile_expression(e)?;
+                self.emit_spanned(Opcode::Index, &[], *s);
+            },
+            Expression::PrefixExpression(i, e, s)   => {
+                self.compile_expression(e)?;
+                match i {
+                    Prefix::Not         => self.emit_spanned(Opcode::Bang, &[], *s),
+                    Prefix::PrefixMinus => self.emit_spanned(Opcode::Minus, &[], *s),
+                    Prefix::BitNot      => self.emit_spanned(Opcode::BitNot, &[], *s)
+                };
+            },
+            Expression::InfixExpression(i, e @ (Infix::And | Infix::Or), a, s) => self.compile_logical(i, e, a, *s)?,
+            Expression::InfixExpression(i, e, a, s) => {
+                self.compile_expression(i)?;
+                self.compile_expression(a)?;
+                self.emit_spanned(Opcode::Infix, &[infix_operand(e)], *s);
+            },
+            Expression::IfExpression(i, e, a, s)    => {
+                self.compile_expression(i)?;
+                let jump_not_truthy = self.emit_spanned(Opcode::JumpNotTruthy, &[0], *s);
+
+                self.compile_block(e)?;
+                let jump = self.emit(Opcode::Jump, &[0]);
+
+                // only one of the branches leaves its value
+                self.current().depth -= 1;
+                self.patch_jump(jump_not_truthy);
+                match a {
+                    Some(a) => self.compile_block(a)?,
+                    None    => {self.emit(Opcode::Null, &[]);}
+                }
+                self.patch_jump(jump);
+            },
+            Expression::RangeExpression(i, e, a, s) => {
+                self.compile_expression(i)?;
+                self.compile_expression(e)?;
+                self.emit_spanned(Opcode::Range, &[*a as usize], *s);
+            },
+            Expression::AssignExpression(i, e, a, s) => self.compile_assign(i, e.as_ref(), a, *s)?,
+            Expression::FunctionLiteral(i, a)       => self.compile_function_literal(i, a)?,
+            Expression::CallExpression(i, a, s)     => {
+                self.compile_expression(i)?;
+
+                let args = a.as_deref().unwrap_or_default();
+                for arg in args {
+                    self.compile_expression(arg)?;
+                }
+                self.emit_spanned(Opcode::Call, &[check(args.len(), MAX_U8, "too many arguments")?], *s);
+            }
+        };
+        Ok(())
+    }
+
+    // JumpNotTruthy both branches on the operands and checks they are booleans
+    fn compile_logical(&mut self, left: &Expression, infix: &Infix, right: &Expression, span: Span) -> Result<(), Error> {
+        self.compile_expression(left)?;
+        let left_false = self.emit_spanned(Opcode::JumpNotTruthy, &[0], span);
+
+        let mut left_true = None;
+        if *infix == Infix::Or {
+            left_true = Some(self.emit(Opcode::Jump, &[0]));
+            self.patch_jump(left_false);
+        }
+
+        self.compile_expression(right)?;
+        let right_false = self.emit_spanned(Opcode::JumpNotTruthy, &[0], span);
+
+        if let Some(i) = left_true {
+            self.patch_jump(i);
+        }
+        self.emit(Opcode::True, &[]);
+        let end = self.emit(Opcode::Jump, &[0]);
+
+        if *infix == Infix::And {
+            self.patch_jump(left_false);
+        }
+        self.patch_jump(right_false);
+        self.current().depth -= 1;
+        self.emit(Opcode::False, &[]);
+        self.patch_jump(end);
+
+        Ok(())
+    }
+
+    // Update leaves the value assigned under the new value of the variable,
+    // which the set instruction then takes
+    fn compile_assign(&mut self, target: &Expression, infix: Option<&Infix>, value: &Expression, span: Span) -> Result<(), Error> {
+        let (name, name_span, indices) = assignment_path(target);
+
+        // the variable's current value goes under the indices for a compound
+        // assignment and on top of the value for a plain one, see eval_assign_expression
+        if infix.is_some() {
+            self.compile_identifier(name, name_span)?;
+        }
+        for index in &indices {
+            self.compile_expression(index)?;
+        }
+        self.compile_expression(value)?;
+
+        if infix.is_none() && indices.is_empty() {
+            self.emit(Opcode::Null, &[]);
+        } else if infix.is_none() {
+            self.compile_identifier(name, name_span)?;
+        }
+
+        let operator = infix.map_or(NO_INFIX, infix_operand);
+        self.emit_spanned(Opcode::Update, &[check(indices.len(), MAX_U8, "too many indices")?, operator], span);
+
+        // a name that isn't known yet may still be declared as a global before this
+        // runs, but a global slot for a builtin would hide the builtin
+        let symbol = match self.symbols.resolve(name) {
+            Some(i) => i,
+            None if builtins::lookup(name).is_some() => {
+                self.emit(Opcode::Pop, &[]);
+                let message = self.intern(Object::String(format!("cannot assign to undeclared variable {}", name)))?;
+                self.emit_spanned(Opcode::Raise, &[message], span);
+                return Ok(())
+            },
+            None    => self.symbols.define_global(name)
+        };
+
+        match symbol.scope {
+            Scope::Global => self.emit_spanned(Opcode::AssignGlobal, &[check(symbol.index, MAX_U16, "too many globals")?], span),
+            Scope::Local  => self.emit(Opcode::SetLocal, &[symbol.index]),
+            Scope::Free   => self.emit(Opcode::SetFree, &[symbol.index])
+        };
+        Ok(())
+    }
+
+    fn compile_identifier(&mut self, name: &str, span: Span) -> Result<(), Error> {
+        let symbol = match self.symbols.resolve(name) {
+            Some(i) => i,
+            None    => match builtins::lookup(name) {
+                Some(i) => return self.emit_constant(i),
+                None    => self.symbols.define_global(name)
+            }
+        };
+
+        match symbol.scope {
+            Scope::Global => self.emit_spanned(Opcode::GetGlobal, &[check(symbol.index, MAX_U16, "too many globals")?], span),
+            Scope::Local  => self.emit(Opcode::GetLocal, &[symbol.index]),
+            Scope::Free   => self.emit(Opcode::GetFree, &[symbol.index])
+        };
+        Ok(())
+    }
+
+    fn compile_function_literal(&mut self, params: &[String], body: &[Statement]) -> Result<(), Error> {
+        self.enter_scope();
+        for param in params {
+            self.symbols.define(param);
+        }
+
+        self.compile_block(body)?;
+        self.emit(Opcode::ReturnValue, &[]);
+
+        let num_locals = self.symbols.num_locals;
+        let (scope, free) = self.leave_scope();
+
+        let function = CompiledFunction{
+            instructions: scope.instructions,
+            num_locals:   check(num_locals, MAX_U8 + 1, "too many local variables")?,
+            num_params:   check(params.len(), MAX_U8, "too many parameters")?,
+            spans:        scope.spans
+        };
+
+        let index = self.add_constant(Constant::Function(Rc::new(function)))?;
+        self.emit(Opcode::Closure, &[index, check(free.len(), MAX_U8, "too many captured variables")?]);
+
+        for symbol in free {
+            let is_local = (symbol.scope == Scope::Local) as u8;
+            self.current().instructions.extend_from_slice(&[is_local, symbol.index as u8]);
+        }
+        Ok(())
+    }
+
+    fn emit_set(&mut self, symbol: Symbol) -> Result<(), Error> {
+        match symbol.scope {
+            Scope::Global => self.emit(Opcode::SetGlobal, &[check(symbol.index, MAX_U16, "too many globals")?]),
+            _             => self.emit(Opcode::SetLocal, &[check(symbol.index, MAX_U8, "too many local variables")?])
+        };
+        Ok(())
+    }
+
+    // Binds a new value to the slot instead of updating the captured one
+    fn emit_define(&mut self, symbol: Symbol) -> Result<(), Error> {
+        self.emit(Opcode::DefineLocal, &[check(symbol.index, MAX_U8, "too many local variables")?]);
+        Ok(())
+    }
+
+    fn emit_constant(&mut self, obj: Object) -> Result<(), Error> {
+        let index = self.intern(obj)?;
+        self.emit(Opcode::Constant, &[index]);
+        Ok(())
+    }
+
+    // Index of the constant holding `obj`, added if it isn't there yet
+    fn intern(&mut self, obj: Object) -> Result<usize, Error> {
+        let key = constant_key(&obj);
+
+        if let Some(i) = key.as_ref().and_then(|i| self.interned.get(i)) {
+            return Ok(*i)
+        }
+
+        let index = self.add_constant(Constant::Object(obj))?;
+        if let Some(key) = key {
+            self.interned.insert(key, index);
+        }
+        Ok(index)
+    }
+
+    fn add_constant(&mut self, constant: Constant) -> Result<usize, Error> {
+        self.constants.push(constant);
+        check(self.constants.len() - 1, MAX_U32, "too many constants")
+    }
+
+    fn emit(&mut self, op: Opcode, operands: &[usize]) -> usize {
+        let scope = self.current();
+        let position = scope.instructions.len();
+
+        scope.instructions.extend(make(op, operands));
+        scope.depth += op.stack_effect(operands);
+        position
+    }
+
+    fn emit_spanned(&mut self, op: Opcode, operands: &[usize], span: Span) -> usize {
+        let position = self.emit(op, operands);
+        self.current().spans.push((position, span));
+        position
+    }
+
+    // Points the jump at `position` to the next instruction
+    // The jump target is the last operand of the instruction at `position`
+    fn patch_jump(&mut self, position: usize) {
+        let scope = self.current();
+        let target = (scope.instructions.len() as u32).to_be_bytes();
+
+        let op = Opcode::from_byte(scope.instructions[position]);
+        let end = position + 1 + op.operand_widths().iter().sum::<usize>();
+
+        scope.instructions[end - 4..end].copy_from_slice(&target);
+    }
+
+    // Hands back the globals and constants for the next run, even after an error
+    pub fn into_state(self) -> (SymbolTable, Vec<Constant>) {
+        let mut symbols = self.symbols;
+
+        while let Some(outer) = symbols.outer.take() {
+            symbols = *outer;
+        }
+        // only the main frame of the failed run had those
+        symbols.num_locals = 0;
+
+        (symbols, self.constants)
+    }
+
+    fn current(&mut self) -> &mut CompilationScope {
+        self.scopes.last_mut().unwrap()
+    }
+
+    fn enter_scope(&mut self) {
+        self.scopes.push(CompilationScope::default());
+
+        let outer = std::mem::take(&mut self.symbols);
+        self.symbols = symbol_table::new_enclosed(outer);
+    }
+
+    fn leave_scope(&mut self) -> (CompilationScope, Vec<Symbol>) {
+        let scope = self.scopes.pop().unwrap_or_default();
+
+        let inner = std::mem::take(&mut self.symbols);
+        self.symbols = *inner.outer.unwrap_or_default();
+
+        (scope, inner.free)
+    }
+
+}
+
+fn infix_operand(infix: &Infix) -> usize {
+    INFIXES.iter().position(|i| i == infix).unwrap()
+}
+
+fn check(value: usize, max: usize, msg: &str) -> Result<usize, Error> {
+    if value > max {Err(err(msg, Span::default()))} else {Ok(value)}
+}
@@ -0,0 +1,135 @@
+use std::collections::HashMap;
+
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum Scope {
+    Global,
+    Local,
+    Free
+}
+
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub struct Symbol {
+    pub scope: Scope,
+    pub index: usize
+}
+
+#[derive(Default)]
+pub struct SymbolTable {
+    pub outer:       Option<Box<SymbolTable>>,
+    // symbols of the enclosing scope captured by this one, in free index order
+    pub free:        Vec<Symbol>,
+    pub num_locals:  usize,
+    store:           HashMap<String, Symbol>,
+    // scopes of the loop bodies being compiled, innermost last. Their names
+    // are locals even at the top level, where they live in the main frame
+    blocks:          Vec<HashMap<String, Symbol>>,
+    // names of the globals in index order, only kept by the outermost table
+    globals:         Vec<String>
+}
+
+pub fn new() -> SymbolTable {
+    SymbolTable::default()
+}
+
+pub fn new_enclosed(outer: SymbolTable) -> SymbolTable {
+    SymbolTable{outer: Some(Box::new(outer)), ..SymbolTable::default()}
+}
+
+impl SymbolTable {
+
+    // `let` in a scope that already bound the name reuses its slot, so
+    // closures that captured the old binding see the new value
+    pub fn define(&mut self, name: &str) -> Symbol {
+        if let Some(block) = self.blocks.last_mut() {
+            return match block.get(name) {
+                Some(i) => *i,
+                None    => {
+                    let symbol = Symbol{scope: Scope::Local, index: self.num_locals};
+                    self.num_locals += 1;
+                    block.insert(name.to_string(), symbol);
+                    symbol
+                }
+            }
+        }
+
+        if self.outer.is_none() {
+            return self.define_global(name)
+        }
+
+        match self.store.get(name) {
+            Some(i) if i.scope == Scope::Local => *i,
+            _                                  => {
+                let symbol = Symbol{scope: Scope::Local, index: self.num_locals};
+                self.num_locals += 1;
+                self.store.insert(name.to_string(), symbol);
+                symbol
+            }
+        }
+    }
+
+    pub fn enter_block(&mut self) {
+        self.blocks.push(HashMap::new());
+    }
+
+    pub fn leave_block(&mut self) {
+        self.blocks.pop();
+    }
+
+    // Whether `define` would give the name a new slot in the innermost block
+    pub fn is_new_in_block(&self, name: &str) -> bool {
+        self.blocks.last().is_some_and(|i| !i.contains_key(name))
+    }
+
+    pub fn resolve(&mut self, name: &str) -> Option<Symbol> {
+        if let Some(i) = self.blocks.iter().rev().find_map(|i| i.get(name)) {
+            return Some(*i)
+        }
+
+        if let Some(i) = self.store.get(name) {
+            return Some(*i)
+        }
+
+        let outer = self.outer.as_mut()?.resolve(name)?;
+
+        if outer.scope == Scope::Global {
+            return Some(outer)
+        }
+
+        let symbol = Symbol{scope: Scope::Free, index: self.free.len()};
+        self.free.push(outer);
+        self.store.insert(name.to_string(), symbol);
+        Some(symbol)
+    }
+
+    // Globals can be referenced before their `let`, as in the evaluator,
+    // so unknown names get a global slot that is filled in at runtime
+    pub fn define_global(&mut self, name: &str) -> Symbol {
+        if let Some(outer) = self.outer.as_mut() {
+            return outer.define_global(name)
+        }
+
+        if let Some(i) = self.store.get(name) {
+            return *i
+        }
+
+        let symbol = Symbol{scope: Scope::Global, index: self.globals.len()};
+        self.globals.push(name.to_string());
+        self.store.insert(name.to_string(), symbol);
+        symbol
+    }
+
+    pub fn resolve_global(&self, name: &str) -> Option<Symbol> {
+        match &self.outer {
+            Some(i) => i.resolve_global(name),
+            None    => self.store.get(name).copied()
+        }
+    }
+
+    pub fn global_names(&self) -> &[String] {
+        match &self.outer {
+            Some(i) => i.global_names(),
+            None    => &self.globals
+        }
+    }
+
+}
@@ -0,0 +1,88 @@
+use std::fmt;
+
+// Location of a piece of source: byte offsets plus the line/column of the start
+#[derive(PartialEq, Clone, Copy, Default, Debug)]
+pub struct Span {
+    pub start:  usize,
+    pub end:    usize,
+    pub line:   usize,
+    pub column: usize
+}
+
+#[derive(PartialEq, Clone, Debug)]
+pub enum Error {
+    Lex(String, Span),
+    Parse(String, Span),
+    Compile(String, Span),
+    Runtime(String, Span)
+}
+
+impl Error {
+
+    // Runtime error without a location, e.g. raised by a host function
+    pub fn runtime(err: &str) -> Error {
+        Error::Runtime(String::from(err), Span::default())
+    }
+
+    // Gives errors raised without a location the span of the call that caused them
+    pub fn or_span(self, span: Span) -> Error {
+        match self {
+            Error::Runtime(i, s) if s == Span::default() => Error::Runtime(i, span),
+            i                                            => i
+        }
+    }
+
+    pub fn span(&self) -> Span {
+        match self {
+            Error::Lex(_, i) | Error::Parse(_, i) | Error::Compile(_, i) | Error::Runtime(_, i) => *i
+        }
+    }
+
+    // rustc-style diagnostic with the offending line and a caret underline
+    pub fn render(&self, source: &str) -> String {
+        let span = self.span();
+
+        // no location to show, e.g. an error raised by a host function
+        if span.line == 0 {
+            return format!("{}", self)
+        }
+
+        let mut out = format!("{}\n", self);
+
+        // errors at the end of input may sit on a final, empty line
+        let line = source.lines().nth(span.line.saturating_sub(1)).unwrap_or("");
+
+        let number = span.line.to_string();
+        let gutter = " ".repeat(number.len());
+
+        let padding: String = line.chars()
+            .take(span.column.saturating_sub(1))
+            .map(|i| if i == '\t' {'\t'} else {' '})
+            .collect();
+
+        let underline = source.get(span.start..span.end)
+            .map(|i| i.chars().take_while(|i| *i != '\n').count())
+            .unwrap_or(0)
+            .max(1);
+
+        out.push_str(&format!("{}--> {}:{}\n", gutter, span.line, span.column));
+        out.push_str(&format!("{} |\n", gutter));
+        out.push_str(&format!("{} | {}\n", number, line));
+        out.push_str(&format!("{} | {}{}", gutter, padding, "^".repeat(underline)));
+        out
+    }
+
+}
+
+impl fmt::Display for Error {
+
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Lex(i, _)     => write!(f, "Lexer error: {}", i),
+            Error::Parse(i, _)   => write!(f, "Parser error: {}", i),
+            Error::Compile(i, _) => write!(f, "Compile error: {}", i),
+            Error::Runtime(i, _) => write!(f, "Runtime error: {}", i)
+        }
+    }
+
+}
@@ -0,0 +1,447 @@
+use std::cmp::Ordering;
+use std::convert::TryFrom;
+use std::fmt;
+
+// Each limb holds nine decimal digits, which keeps Display exact and cheap
+const BASE: u64 = 1_000_000_000;
+
+// Arbitrary-precision integer: sign plus magnitude in little-endian base 10^9 limbs.
+// Always normalized (no leading zero limbs, zero is never negative) so the derived
+// equality and hashing compare values
+#[derive(PartialEq, Eq, Hash, Clone, Debug)]
+pub struct BigInt {
+    negative: bool,
+    limbs:    Vec<u32>
+}
+
+impl From<i64> for BigInt {
+
+    fn from(value: i64) -> BigInt {
+        let mut magnitude = value.unsigned_abs();
+        let mut limbs = Vec::new();
+
+        while magnitude > 0 {
+            limbs.push((magnitude % BASE) as u32);
+            magnitude /= BASE;
+        }
+
+        BigInt{negative: value < 0, limbs}
+    }
+
+}
+
+impl BigInt {
+
+    fn new(negative: bool, mut limbs: Vec<u32>) -> BigInt {
+        while limbs.last() == Some(&0) {
+            limbs.pop();
+        }
+
+        BigInt{negative: negative && !limbs.is_empty(), limbs}
+    }
+
+    // Digits in the given radix with an optional sign, as in i64::from_str_radix
+    pub fn parse(text: &str, radix: u32) -> Option<BigInt> {
+        let (negative, digits) = match text.strip_prefix('-') {
+            Some(i) => (true, i),
+            None    => (false, text.strip_prefix('+').unwrap_or(text))
+        };
+
+        if digits.is_empty() {
+            return None
+        }
+
+        let radix_big = BigInt::from(radix as i64);
+        let mut value = BigInt::from(0);
+
+        for chr in digits.chars() {
+            value = value.mul(&radix_big).add(&BigInt::from(chr.to_digit(radix)? as i64));
+        }
+
+        Some(if negative {value.neg()} else {value})
+    }
+
+    // The value as an i64, when it fits
+    pub fn to_i64(&self) -> Option<i64> {
+        let mut magnitude: i128 = 0;
+
+        for limb in self.limbs.iter().rev() {
+            magnitude = magnitude * BASE as i128 + *limb as i128;
+
+            if magnitude > i64::MAX as i128 + 1 {
+                return None
+            }
+        }
+
+        let value = if self.negative {-magnitude} else {magnitude};
+        i64::try_from(value).ok()
+    }
+
+    // Going through the decimal form rounds correctly, unlike summing the limbs
+    pub fn to_f64(&self) -> f64 {
+        self.to_string().parse().unwrap_or(f64::NAN)
+    }
+
+    pub fn pow(&self, mut exponent: u32) -> BigInt {
+        let mut base = self.clone();
+        let mut result = BigInt::from(1);
+
+        while exponent > 0 {
+            if exponent & 1 == 1 {
+                result = result.mul(&base);
+            }
+            exponent >>= 1;
+            if exponent > 0 {
+                base = base.mul(&base);
+            }
+        }
+
+        result
+    }
+
+    // Base 2 logarithm of the magnitude from its two leading limbs, close enough
+    // to size a result before computing it. Zero gives 0 like one does
+    pub fn log2(&self) -> f64 {
+        let mut limbs = self.limbs.iter().rev();
+
+        let top = match limbs.next() {
+            Some(i) => *i as f64,
+            None    => return 0.0
+        };
+        let next = limbs.next().map_or(0.0, |i| *i as f64 / BASE as f64);
+
+        (top + next).log2() + (self.limbs.len() - 1) as f64 * (BASE as f64).log2()
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.limbs.is_empty()
+    }
+
+    pub fn neg(&self) -> BigInt {
+        BigInt::new(!self.negative, self.limbs.clone())
+    }
+
+    pub fn add(&self, other: &BigInt) -> BigInt {
+        if self.negative == other.negative {
+            return BigInt::new(self.negative, add_magnitudes(&self.limbs, &other.limbs))
+        }
+
+        match cmp_magnitudes(&self.limbs, &other.limbs) {
+            Ordering::Less => BigInt::new(other.negative, sub_magnitudes(&other.limbs, &self.limbs)),
+            _              => BigInt::new(self.negative, sub_magnitudes(&self.limbs, &other.limbs))
+        }
+    }
+
+    pub fn sub(&self, other: &BigInt) -> BigInt {
+        self.add(&other.neg())
+    }
+
+    pub fn mul(&self, other: &BigInt) -> BigInt {
+        let mut limbs = vec![0u64; self.limbs.len() + other.limbs.len()];
+
+        for (i, a) in self.limbs.iter().enumerate() {
+            let mut carry = 0;
+
+            for (j, b) in other.limbs.iter().enumerate() {
+                let cur = limbs[i + j] + *a as u64 * *b as u64 + carry;
+                limbs[i + j] = cur % BASE;
+                carry = cur / BASE;
+            }
+
+            let mut k = i + other.limbs.len();
+            while carry > 0 {
+                let cur = limbs[k] + carry;
+                limbs[k] = cur % BASE;
+                carry = cur / BASE;
+                k += 1;
+            }
+        }
+
+        BigInt::new(self.negative != other.negative, limbs.into_iter().map(|i| i as u32).collect())
+    }
+
+    // Quotient and remainder truncated toward zero, like i64. None when dividing by zero
+    pub fn div_rem(&self, other: &BigInt) -> Option<(BigInt, BigInt)> {
+        if other.is_zero() {
+            return None
+        }
+
+        let mut quotient  = vec![0u32; self.limbs.len()];
+        let mut remainder = Vec::new();
+
+        // schoolbook long division, one limb at a time, binary searching each quotient limb
+        for i in (0..self.limbs.len()).rev() {
+            remainder.insert(0, self.limbs[i]);
+            remainder = BigInt::new(false, remainder).limbs;
+
+            let (mut low, mut high) = (0, BASE - 1);
+            while low < high {
+                let mid = (low + high).div_ceil(2);
+
+                if cmp_magnitudes(&mul_small(&other.limbs, mid), &remainder) == Ordering::Greater {
+                    high = mid - 1;
+                } else {
+                    low = mid;
+                }
+            }
+
+            quotient[i] = low as u32;
+            remainder = sub_magnitudes(&remainder, &mul_small(&other.limbs, low));
+        }
+
+        Some((
+            BigInt::new(self.negative != other.negative, quotient),
+            BigInt::new(self.negative, remainder)
+        ))
+    }
+
+
+    pub fn bit_and(&self, other: &BigInt) -> BigInt {
+        self.bitwise(other, |a, b| a & b)
+    }
+
+    pub fn bit_or(&self, other: &BigInt) -> BigInt {
+        self.bitwise(other, |a, b| a | b)
+    }
+
+    pub fn bit_xor(&self, other: &BigInt) -> BigInt {
+        self.bitwise(other, |a, b| a ^ b)
+    }
+
+    // Applies `op` to the two's complement forms, with one extra word so the
+    // sign bit of both operands survives
+    fn bitwise(&self, other: &BigInt, op: fn(u32, u32) -> u32) -> BigInt {
+        let (a, b) = (self.to_words(), other.to_words());
+        let len = a.len().max(b.len()) + 1;
+
+        let words: Vec<u32> = twos_complement(a, self.negative, len).into_iter()
+            .zip(twos_complement(b, other.negative, len))
+            .map(|(a, b)| op(a, b))
+            .collect();
+
+        let negative = words[len - 1] >> 31 == 1;
+        // the magnitude of a negative result is its two's complement again
+        let words = if negative {twos_complement(words, true, len)} else {words};
+
+        let base = BigInt::from(1 << 32);
+        let magnitude = words.iter().rev()
+            .fold(BigInt::from(0), |acc, i| acc.mul(&base).add(&BigInt::from(*i as i64)));
+
+        if negative {magnitude.neg()} else {magnitude}
+    }
+
+    // Magnitude in little-endian base 2^32 words
+    fn to_words(&self) -> Vec<u32> {
+        let mut words: Vec<u32> = Vec::new();
+
+        for limb in self.limbs.iter().rev() {
+            let mut carry = *limb as u64;
+
+            for word in words.iter_mut() {
+                let cur = *word as u64 * BASE + carry;
+                *word = cur as u32;
+                carry = cur >> 32;
+            }
+            if carry > 0 {
+                words.push(carry as u32);
+            }
+        }
+
+        words
+    }
+
+}
+
+// The words of -magnitude in two's complement when `negative` is set,
+// which is the bits inverted plus one, padded to `len` words
+fn twos_complement(mut words: Vec<u32>, negative: bool, len: usize) -> Vec<u32> {
+    words.resize(len, 0);
+
+    if negative {
+        let mut carry = true;
+
+        for word in words.iter_mut() {
+            let (sum, overflow) = (!*word).overflowing_add(carry as u32);
+            *word = sum;
+            carry = overflow;
+        }
+    }
+
+    words
+}
+
+impl PartialOrd for BigInt {
+
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+
+}
+
+impl Ord for BigInt {
+
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self.negative, other.negative) {
+            (false, true)  => Ordering::Greater,
+            (true, false)  => Ordering::Less,
+            (false, false) => cmp_magnitudes(&self.limbs, &other.limbs),
+            (true, true)   => cmp_magnitudes(&other.limbs, &self.limbs)
+        }
+    }
+
+}
+
+impl fmt::Display for BigInt {
+
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut limbs = self.limbs.iter().rev();
+
+        match limbs.next() {
+            Some(i) => write!(f, "{}{}", if self.negative {"-"} else {""}, i)?,
+            None    => return write!(f, "0")
+        }
+
+        for limb in limbs {
+            write!(f, "{:09}", limb)?;
+        }
+        Ok(())
+    }
+
+}
+
+fn cmp_magnitudes(a: &[u32], b: &[u32]) -> Ordering {
+    a.len().cmp(&b.len())
+        .then_with(|| a.iter().rev().cmp(b.iter().rev()))
+}
+
+fn add_magnitudes(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut limbs = Vec::with_capacity(a.len().max(b.len()) + 1);
+    let mut carry = 0;
+
+    for i in 0..a.len().max(b.len()) {
+        let cur = *a.get(i).unwrap_or(&0) as u64 + *b.get(i).unwrap_or(&0) as u64 + carry;
+        limbs.push((cur % BASE) as u32);
+        carry = cur / BASE;
+    }
+
+    if carry > 0 {
+        limbs.push(carry as u32);
+    }
+    limbs
+}
+
+// a - b, where a >= b
+fn sub_magnitudes(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut limbs = Vec::with_capacity(a.len());
+    let mut borrow = 0;
+
+    for (i, limb) in a.iter().enumerate() {
+        let mut cur = *limb as i64 - *b.get(i).unwrap_or(&0) as i64 - borrow;
+
+        borrow = if cur < 0 {cur += BASE as i64; 1} else {0};
+        limbs.push(cur as u32);
+    }
+
+    BigInt::new(false, limbs).limbs
+}
+
+fn mul_small(a: &[u32], n: u64) -> Vec<u32> {
+    let mut limbs = Vec::with_capacity(a.len() + 1);
+    let mut carry = 0;
+
+    for limb in a {
+        let cur = *limb as u64 * n + carry;
+        limbs.push((cur % BASE) as u32);
+        carry = cur / BASE;
+    }
+
+    while carry > 0 {
+        limbs.push((carry % BASE) as u32);
+        carry /= BASE;
+    }
+
+    BigInt::new(false, limbs).limbs
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn big(text: &str) -> BigInt {
+        BigInt::parse(text, 10).unwrap()
+    }
+
+    #[test]
+    fn parse() {
+        let cases = &[
+            ("0",                      10, "0"),
+            ("-0",                     10, "0"),
+            ("+42",                    10, "42"),
+            ("123456789012345678901",  10, "123456789012345678901"),
+            ("-1000000000",            10, "-1000000000"),
+            ("ffffffffffffffffffff",   16, "1208925819614629174706175"),
+            ("-777",                   8,  "-511"),
+            ("1111111111111111111111111111111111111111111111111111111111111111111111", 2, "1180591620717411303423")
+        ];
+
+        for (text, radix, expected) in cases {
+            assert_eq!(BigInt::parse(text, *radix).unwrap().to_string(), *expected, "{}", text);
+        }
+
+        for text in &["", "-", "12x", "1_000"] {
+            assert_eq!(BigInt::parse(text, 10), None, "{}", text);
+        }
+        assert_eq!(BigInt::parse("102", 2), None);
+    }
+
+    #[test]
+    fn div_rem() {
+        // truncated toward zero, remainder takes the sign of the dividend
+        let cases = &[
+            ("100000000000000000000",  "7",                  "14285714285714285714",  "2"),
+            ("-100000000000000000000", "7",                  "-14285714285714285714", "-2"),
+            ("100000000000000000000",  "-7",                 "-14285714285714285714", "2"),
+            ("-100000000000000000000", "-7",                 "14285714285714285714",  "-2"),
+            ("5",                      "100000000000000000", "0",                     "5"),
+            ("-5",                     "100000000000000000", "0",                     "-5"),
+            ("1000000000000000000000", "1000000000",         "1000000000000",         "0"),
+            ("1000000000000000000000", "999999999999",       "1000000000",            "1000000000")
+        ];
+
+        for (a, b, quotient, remainder) in cases {
+            let (q, r) = big(a).div_rem(&big(b)).unwrap();
+
+            assert_eq!(q.to_string(), *quotient, "{} / {}", a, b);
+            assert_eq!(r.to_string(), *remainder, "{} % {}", a, b);
+            assert_eq!(q.mul(&big(b)).add(&r), big(a), "{} / {}", a, b);
+        }
+
+        assert_eq!(big("123456789012345678901").div_rem(&big("0")), None);
+    }
+
+    #[test]
+    fn bitwise() {
+        // results as i128, which has two's complement semantics to compare against
+        let values: &[i128] = &[0, 1, -1, 12345, -12345, 1 << 64, -(1 << 64), (1 << 70) - 1, -(1 << 70) + 3];
+
+        for a in values {
+            for b in values {
+                let (x, y) = (big(&a.to_string()), big(&b.to_string()));
+
+                assert_eq!(x.bit_and(&y).to_string(), (a & b).to_string(), "{} & {}", a, b);
+                assert_eq!(x.bit_or(&y).to_string(), (a | b).to_string(), "{} | {}", a, b);
+                assert_eq!(x.bit_xor(&y).to_string(), (a ^ b).to_string(), "{} ^ {}", a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn ordering() {
+        assert!(big("-100000000000000000000") < big("-99999999999999999999"));
+        assert!(big("-1") < big("0"));
+        assert!(big("99999999999999999999") < big("100000000000000000000"));
+        assert_eq!(big("-0"), big("0"));
+    }
+
+}
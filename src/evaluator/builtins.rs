@@ -0,0 +1,188 @@
+use crate::evaluator::object::Object;
+use crate::evaluator::bigint::BigInt;
+use crate::error::Error;
+
+// Also given whether big integers are enabled, see Interpreter::set_big_integers
+pub type BuiltinFn = fn(Vec<Object>, bool) -> Result<Object, String>;
+
+pub type NativeFn = dyn Fn(&[Object]) -> Result<Object, Error>;
+
+pub struct Builtin {
+    pub name: &'static str,
+    pub func: BuiltinFn
+}
+
+impl PartialEq for Builtin {
+
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name
+    }
+
+}
+
+// Host function registered by an embedder
+pub struct NativeFunction {
+    pub name: String,
+    pub func: Box<NativeFn>
+}
+
+impl PartialEq for NativeFunction {
+
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+
+}
+
+static BUILTINS: &[Builtin] = &[
+    Builtin{name: "puts",  func: puts},
+    Builtin{name: "len",   func: len},
+    Builtin{name: "first", func: first},
+    Builtin{name: "last",  func: last},
+    Builtin{name: "rest",  func: rest},
+    Builtin{name: "push",  func: push},
+    Builtin{name: "type",  func: type_of},
+    Builtin{name: "int",   func: int},
+    Builtin{name: "float", func: float},
+    Builtin{name: "round", func: round},
+];
+
+pub fn lookup(name: &str) -> Option<Object> {
+    BUILTINS.iter()
+        .find(|i| i.name == name)
+        .map(Object::Builtin)
+}
+
+fn check_arity(name: &str, args: &[Object], expected: usize) -> Result<(), String> {
+    if args.len() != expected {
+        Err(format!("wrong number of arguments to {}: expected {}, got {}", name, expected, args.len()))
+    } else {
+        Ok(())
+    }
+}
+
+fn unsupported(name: &str, obj: &Object) -> String {
+    format!("argument to {} not supported, got {}", name, obj.type_name())
+}
+
+fn puts(args: Vec<Object>, _: bool) -> Result<Object, String> {
+    for arg in args {
+        println!("{}", arg);
+    }
+
+    Ok(Object::Null)
+}
+
+fn len(args: Vec<Object>, _: bool) -> Result<Object, String> {
+    check_arity("len", &args, 1)?;
+
+    Ok(Object::Integer(match &args[0] {
+        Object::String(i) => i.chars().count(),
+        Object::Array(i)  => i.len(),
+        Object::Hash(i)   => i.len(),
+        i                 => return Err(unsupported("len", i))
+    } as i64))
+}
+
+fn first(args: Vec<Object>, _: bool) -> Result<Object, String> {
+    check_arity("first", &args, 1)?;
+
+    match &args[0] {
+        Object::Array(i) => Ok(i.first().cloned().unwrap_or(Object::Null)),
+        i                => Err(unsupported("first", i))
+    }
+}
+
+fn last(args: Vec<Object>, _: bool) -> Result<Object, String> {
+    check_arity("last", &args, 1)?;
+
+    match &args[0] {
+        Object::Array(i) => Ok(i.last().cloned().unwrap_or(Object::Null)),
+        i                => Err(unsupported("last", i))
+    }
+}
+
+fn rest(args: Vec<Object>, _: bool) -> Result<Object, String> {
+    check_arity("rest", &args, 1)?;
+
+    match &args[0] {
+        Object::Array(i) if i.is_empty() => Ok(Object::Null),
+        Object::Array(i)                 => Ok(Object::Array(i[1..].to_vec())),
+        i                                => Err(unsupported("rest", i))
+    }
+}
+
+fn push(mut args: Vec<Object>, _: bool) -> Result<Object, String> {
+    check_arity("push", &args, 2)?;
+
+    let value = args.pop().unwrap();
+    match args.pop().unwrap() {
+        Object::Array(mut i) => {
+            i.push(value);
+            Ok(Object::Array(i))
+        },
+        i                    => Err(unsupported("push", &i))
+    }
+}
+
+fn type_of(args: Vec<Object>, _: bool) -> Result<Object, String> {
+    check_arity("type", &args, 1)?;
+
+    Ok(Object::String(args[0].type_name().to_string()))
+}
+
+fn int(args: Vec<Object>, big_integers: bool) -> Result<Object, String> {
+    check_arity("int", &args, 1)?;
+
+    match &args[0] {
+        i @ Object::Integer(_)    => Ok(i.clone()),
+        i @ Object::BigInteger(_) => Ok(i.clone()),
+        Object::Float(i)          => float_to_integer(i.trunc()),
+        Object::String(i)         => match (i.trim().parse(), big_integers) {
+            (Ok(i), _)     => Ok(Object::Integer(i)),
+            (Err(_), true) => BigInt::parse(i.trim(), 10)
+                .map(Object::BigInteger)
+                .ok_or_else(|| format!("cannot convert {:?} to integer", i)),
+            (Err(_), _)    => Err(format!("cannot convert {:?} to integer", i))
+        },
+        i                         => Err(unsupported("int", i))
+    }
+}
+
+fn float(args: Vec<Object>, _: bool) -> Result<Object, String> {
+    check_arity("float", &args, 1)?;
+
+    Ok(Object::Float(match &args[0] {
+        Object::Integer(i)    => *i as f64,
+        Object::BigInteger(i) => Some(i.to_f64())
+            .filter(|i| i.is_finite())
+            .ok_or("integer too large for a float")?,
+        Object::Float(i)      => *i,
+        // "inf" and "nan" parse, but have no literal of their own
+        Object::String(i)     => i.trim().parse().ok()
+            .filter(|i: &f64| i.is_finite())
+            .ok_or_else(|| format!("cannot convert {:?} to float", i))?,
+        i                     => return Err(unsupported("float", i))
+    }))
+}
+
+// Rounds half away from zero, returning an integer
+fn round(args: Vec<Object>, _: bool) -> Result<Object, String> {
+    check_arity("round", &args, 1)?;
+
+    match &args[0] {
+        i @ Object::Integer(_)    => Ok(i.clone()),
+        i @ Object::BigInteger(_) => Ok(i.clone()),
+        Object::Float(i)          => float_to_integer(i.round()),
+        i                         => Err(unsupported("round", i))
+    }
+}
+
+fn float_to_integer(value: f64) -> Result<Object, String> {
+    // i64::MAX as f64 rounds up to 2^63, which is already out of range
+    if value.is_finite() && value >= i64::MIN as f64 && value < i64::MAX as f64 {
+        Ok(Object::Integer(value as i64))
+    } else {
+        Err(format!("cannot convert {:?} to integer", value))
+    }
+}
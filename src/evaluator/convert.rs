@@ -0,0 +1,209 @@
+use crate::evaluator::object::{Object, HashKey, HashPairs};
+use crate::error::Error;
+use std::collections::HashMap;
+use std::hash::Hash;
+
+// Conversions between Rust values and script objects, used by host functions
+
+pub trait FromObject: Sized {
+    fn from_object(obj: &Object) -> Result<Self, Error>;
+}
+
+pub trait IntoObject {
+    fn into_object(self) -> Object;
+}
+
+fn expected(type_name: &str, obj: &Object) -> Error {
+    Error::runtime(&format!("expected {}, found {}", type_name, obj.type_name()))
+}
+
+impl FromObject for Object {
+
+    fn from_object(obj: &Object) -> Result<Self, Error> {
+        Ok(obj.clone())
+    }
+
+}
+
+impl FromObject for i64 {
+
+    fn from_object(obj: &Object) -> Result<Self, Error> {
+        match obj {
+            Object::Integer(i)    => Ok(*i),
+            Object::BigInteger(_) => Err(Error::runtime("integer too large for i64")),
+            i                     => Err(expected("integer", i))
+        }
+    }
+
+}
+
+// Integers convert too, since scripts promote them to floats freely
+impl FromObject for f64 {
+
+    fn from_object(obj: &Object) -> Result<Self, Error> {
+        match obj {
+            Object::Float(i)      => Ok(*i),
+            Object::Integer(i)    => Ok(*i as f64),
+            Object::BigInteger(i) => Ok(i.to_f64()),
+            i                     => Err(expected("float", i))
+        }
+    }
+
+}
+
+impl FromObject for bool {
+
+    fn from_object(obj: &Object) -> Result<Self, Error> {
+        match obj {
+            Object::Boolean(i) => Ok(*i),
+            i                  => Err(expected("boolean", i))
+        }
+    }
+
+}
+
+impl FromObject for String {
+
+    fn from_object(obj: &Object) -> Result<Self, Error> {
+        match obj {
+            Object::String(i) => Ok(i.clone()),
+            i                 => Err(expected("string", i))
+        }
+    }
+
+}
+
+impl<T: FromObject> FromObject for Vec<T> {
+
+    fn from_object(obj: &Object) -> Result<Self, Error> {
+        match obj {
+            Object::Array(i) => i.iter().map(T::from_object).collect(),
+            i                => Err(expected("array", i))
+        }
+    }
+
+}
+
+impl<K: FromObject + Eq + Hash, V: FromObject> FromObject for HashMap<K, V> {
+
+    fn from_object(obj: &Object) -> Result<Self, Error> {
+        match obj {
+            Object::Hash(i) => i.iter()
+                .map(|(k, v)| Ok((K::from_object(k)?, V::from_object(v)?)))
+                .collect(),
+            i               => Err(expected("hash", i))
+        }
+    }
+
+}
+
+impl IntoObject for Object {
+
+    fn into_object(self) -> Object {
+        self
+    }
+
+}
+
+impl IntoObject for () {
+
+    fn into_object(self) -> Object {
+        Object::Null
+    }
+
+}
+
+impl IntoObject for i64 {
+
+    fn into_object(self) -> Object {
+        Object::Integer(self)
+    }
+
+}
+
+impl IntoObject for f64 {
+
+    fn into_object(self) -> Object {
+        Object::Float(self)
+    }
+
+}
+
+impl IntoObject for bool {
+
+    fn into_object(self) -> Object {
+        Object::Boolean(self)
+    }
+
+}
+
+impl IntoObject for String {
+
+    fn into_object(self) -> Object {
+        Object::String(self)
+    }
+
+}
+
+impl IntoObject for &str {
+
+    fn into_object(self) -> Object {
+        Object::String(self.to_string())
+    }
+
+}
+
+impl<T: IntoObject> IntoObject for Vec<T> {
+
+    fn into_object(self) -> Object {
+        Object::Array(self.into_iter().map(T::into_object).collect())
+    }
+
+}
+
+// Only keys that can be hashed by scripts are accepted
+impl<K: Into<HashKey>, V: IntoObject> IntoObject for HashMap<K, V> {
+
+    fn into_object(self) -> Object {
+        let mut hash = HashPairs::default();
+
+        for (k, v) in self {
+            hash.insert_key(k.into(), v.into_object());
+        }
+
+        Object::Hash(hash)
+    }
+
+}
+
+impl From<i64> for HashKey {
+
+    fn from(key: i64) -> HashKey {
+        HashKey::Integer(key)
+    }
+
+}
+
+impl From<bool> for HashKey {
+
+    fn from(key: bool) -> HashKey {
+        HashKey::Boolean(key)
+    }
+
+}
+
+impl From<String> for HashKey {
+
+    fn from(key: String) -> HashKey {
+        HashKey::String(key)
+    }
+
+}
+
+impl From<&str> for HashKey {
+
+    fn from(key: &str) -> HashKey {
+        HashKey::String(key.to_string())
+    }
+
+}
@@ -1,21 +1,24 @@
 use crate::evaluator::object::Object;
+use crate::evaluator::builtins;
 use std::collections::HashMap;
 use std::rc::Rc;
 use std::cell::RefCell;
 
-pub type EnvRc<'a> = Rc<RefCell<Environment<'a>>>;
+pub type EnvRc = Rc<RefCell<Environment>>;
 
-#[derive(PartialEq)]
-pub struct Environment<'a> {
-    pub vars:  HashMap<String, Object<'a>>,
-    pub outer: Option<EnvRc<'a>>
+pub struct Environment {
+    pub vars:  HashMap<String, Object>,
+    pub outer: Option<EnvRc>,
+    // only read from the outermost environment, see big_integers()
+    pub big_integers: bool
 }
 
 
-pub fn new<'a>() -> EnvRc<'a> {
+pub fn new() -> EnvRc {
     Rc::new(RefCell::new(Environment{
         vars:  HashMap::new(),
-        outer: None
+        outer: None,
+        big_integers: false
     }))
 }
 
@@ -25,20 +28,42 @@ pub fn new_enclosed_environment(outer: EnvRc) -> EnvRc {
     env
 }
 
-impl<'a> Environment<'a> {
+impl Environment {
 
-    pub fn get(&self, ident: &str) -> Result<Object<'a>, String> {
+    pub fn get(&self, ident: &str) -> Result<Object, String> {
         match self.vars.get(ident) {
             Some(i) => Ok(i.clone()),
             None    => match &self.outer {
-                Some(e) => Ok(e.borrow_mut().get(ident)?),
-                None    => Err(format!("Runtime error: {} not found", ident))
+                Some(e) => Ok(e.borrow().get(ident)?),
+                None    => builtins::lookup(ident)
+                    .ok_or_else(|| format!("{} not found", ident))
             }
         }
     }
 
-    pub fn set(&mut self, ident: &str, obj: Object<'a>) {
+    pub fn big_integers(&self) -> bool {
+        match &self.outer {
+            Some(e) => e.borrow().big_integers(),
+            None    => self.big_integers
+        }
+    }
+
+    pub fn set(&mut self, ident: &str, obj: Object) {
         self.vars.insert(ident.to_string(), obj);
     }
 
+    // Updates the innermost existing binding instead of creating a new one
+    pub fn assign(&mut self, ident: &str, obj: Object) -> Result<(), String> {
+        match self.vars.get_mut(ident) {
+            Some(i) => {
+                *i = obj;
+                Ok(())
+            },
+            None    => match &self.outer {
+                Some(e) => e.borrow_mut().assign(ident, obj),
+                None    => Err(format!("cannot assign to undeclared variable {}", ident))
+            }
+        }
+    }
+
 }
\ No newline at end of file
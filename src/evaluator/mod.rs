@@ -1,16 +1,34 @@
-mod object;
+pub mod object;
+pub mod bigint;
+pub mod convert;
+pub mod builtins;
 pub mod environment;
 
-use object::Object;
+use object::{Object, HashPairs};
+use bigint::BigInt;
 use environment::{EnvRc};
 use crate::parser::{Program, Statement, Expression, Prefix, Infix};
+use crate::error::{Error, Span};
 use std::rc::Rc;
+use std::convert::TryFrom;
+use std::fmt;
 
-pub fn run_program<'a>(program: Program<'a>, env: EnvRc<'a>) -> Result<Object<'a>, String> {
-    Ok(eval_statements_unwrap(program.statements, env)?)
+// Evaluates an operand, handing a return, break or continue from an if
+// expression inside it straight to the enclosing statement
+macro_rules! operand {
+    ($exp:expr, $env:expr) => {
+        match eval_expression($exp, $env)? {
+            i @ (Object::Return(_) | Object::Break | Object::Continue) => return Ok(i),
+            i                                                          => i
+        }
+    }
+}
+
+pub fn run_program(program: Program, env: EnvRc) -> Result<Object, Error> {
+    eval_statements_unwrap(&program.statements, env)
 }
 
-fn eval_statements_unwrap<'a>(stmts: Vec<Statement<'a>>, env: EnvRc<'a>) -> Result<Object<'a>, String> {
+fn eval_statements_unwrap(stmts: &[Statement], env: EnvRc) -> Result<Object, Error> {
     let mut result = Object::Null;
     for stmt in stmts {
 
@@ -22,136 +40,654 @@ fn eval_statements_unwrap<'a>(stmts: Vec<Statement<'a>>, env: EnvRc<'a>) -> Resu
     Ok(result)
 }
 
-fn eval_statements<'a>(stmts: Vec<Statement<'a>>, env: EnvRc<'a>) -> Result<Object<'a>, String> {
+fn eval_statements(stmts: &[Statement], env: EnvRc) -> Result<Object, Error> {
     let mut result = Object::Null;
     for stmt in stmts {
 
         match eval_statement(stmt, Rc::clone(&env))? {
             i @ Object::Return(_) => return Ok(i),
+            i @ Object::Break     => return Ok(i),
+            i @ Object::Continue  => return Ok(i),
             i                     => result = i,
         }
     }
     Ok(result)
 }
 
-fn eval_statement<'a>(stmt: Statement<'a>, env: EnvRc<'a>) -> Result<Object<'a>, String> {
+fn eval_statement(stmt: &Statement, env: EnvRc) -> Result<Object, Error> {
     Ok(match stmt {
         Statement::ExpressionStatement(i) => eval_expression(i, env)?,
-        Statement::ReturnStatement(i)     => Object::Return(Box::new(eval_expression(i, env)?)),
-        Statement::LetStatement(i, a)     => {
-            env.borrow_mut().set(i, eval_expression(a, Rc::clone(&env))?);
+        Statement::ReturnStatement(i)     => Object::Return(Box::new(operand!(i, env))),
+        Statement::LetStatement(i, a, _)  => {
+            let obj = operand!(a, Rc::clone(&env));
+
+            env.borrow_mut().set(i, obj);
             Object::Null
-        }
+        },
+        Statement::WhileStatement(i, a, s) => eval_while_statement(i, a, *s, env)?,
+        Statement::ForStatement(i, e, a, s) => eval_for_statement(i, e, a, *s, env)?,
+        Statement::BreakStatement         => Object::Break,
+        Statement::ContinueStatement      => Object::Continue
     })
 }
 
-fn eval_expression<'a>(exp: Expression<'a>, env: EnvRc<'a>) -> Result<Object<'a>, String> {
+fn eval_while_statement(condition: &Expression, body: &[Statement], span: Span, env: EnvRc) -> Result<Object, Error> {
+    loop {
+        let condition = operand!(condition, Rc::clone(&env));
+
+        match condition {
+            Object::Boolean(true)  => (),
+            Object::Boolean(false) => break,
+            i                      => return Err(Error::Runtime(format!("expected boolean, found {}", i), span))
+        }
+
+        match eval_statements(body, Rc::clone(&env))? {
+            Object::Break         => break,
+            i @ Object::Return(_) => return Ok(i),
+            _                     => ()
+        }
+    }
+
+    Ok(Object::Null)
+}
+
+fn eval_expression(exp: &Expression, env: EnvRc) -> Result<Object, Error> {
     Ok(match exp {
-        Expression::Int(i)                   => Object::Integer(i), 
-        Expression::Bool(i)                  => Object::Boolean(i),
-        Expression::Ident(i)                 => env.borrow_mut().get(i)?,
-        Expression::PrefixExpression(i, e)   => eval_prefix_expression(i, *e, env)?,
-        Expression::InfixExpression(i, e, a) => eval_infix_expression(*i, e, *a, env)?,
-        Expression::IfExpression(i, e, a)    => eval_if_expression(*i, e, a, env)?,
-        Expression::FunctionLiteral(i, a)    => Object::Function(i, a, env),
-        Expression::CallExpression(i, a)     => eval_call_expression(*i, a, env)?
+        Expression::Int(i)                      => Object::Integer(*i), 
+        Expression::BigInt(i)                   => Object::BigInteger(i.clone()),
+        Expression::Float(i)                    => Object::Float(*i),
+        Expression::Str(i)                      => Object::String(i.clone()),
+        Expression::Bool(i)                     => Object::Boolean(*i),
+        Expression::ArrayLiteral(i)             => match eval_expressions(i, env)? {
+            Ok(i)  => Object::Array(i),
+            Err(i) => i
+        },
+        Expression::HashLiteral(i)              => eval_hash_literal(i, env)?,
+        Expression::IndexExpression(i, e, s)    => eval_index_expression(i, e, *s, env)?,
+        Expression::Ident(i, s)                 => env.borrow().get(i).map_err(err_at(*s))?,
+        Expression::PrefixExpression(i, e, s)   => eval_prefix_expression(i, e, *s, env)?,
+        Expression::InfixExpression(i, e, a, s) => eval_infix_expression(i, e, a, *s, env)?,
+        Expression::RangeExpression(i, e, a, s) => {
+            let start = operand!(i, Rc::clone(&env));
+            let end   = operand!(e, env);
+
+            range_object(start, end, *a).map_err(err_at(*s))?
+        },
+        Expression::AssignExpression(i, e, a, s) => eval_assign_expression(i, e.as_ref(), a, *s, env)?,
+        Expression::IfExpression(i, e, a, s)    => eval_if_expression(i, e, a.as_deref(), *s, env)?,
+        Expression::FunctionLiteral(i, a)       => Object::Function(Rc::clone(i), Rc::clone(a), env),
+        Expression::CallExpression(i, a, s)     => eval_call_expression(i, a.as_deref().unwrap_or_default(), *s, env)?
     })
 }
 
-fn eval_call_expression<'a>(exp: Expression<'a>, args: Option<Vec<Expression<'a>>>, env: EnvRc<'a>) -> Result<Object<'a>, String> {
-    let obj = eval_expression(exp, Rc::clone(&env))?;
+// Each iteration binds the loop variables in a new environment, so closures
+// created in the body keep the values of their own iteration
+fn eval_for_statement(names: &[String], iterable: &Expression, body: &[Statement], span: Span, env: EnvRc) -> Result<Object, Error> {
+    let iterable = operand!(iterable, Rc::clone(&env));
+    let iterable = iterable_object(iterable).map_err(err_at(span))?;
+
+    let mut index = 0;
+    while let Some(values) = loop_bindings(&iterable, index, names.len() == 2) {
+        let loop_env = environment::new_enclosed_environment(Rc::clone(&env));
+
+        for (name, value) in names.iter().zip(values) {
+            loop_env.borrow_mut().set(name, value);
+        }
+
+        match eval_statements(body, loop_env)? {
+            Object::Break         => break,
+            i @ Object::Return(_) => return Ok(i),
+            _                     => ()
+        }
+        index += 1;
+    }
+
+    Ok(Object::Null)
+}
+
+pub(crate) fn range_object(start: Object, end: Object, inclusive: bool) -> Result<Object, String> {
+    match (start, end) {
+        (Object::Integer(i), Object::Integer(e)) => Ok(Object::Range(i, e, inclusive)),
+        (Object::Integer(_), i) | (i, _)         => Err(format!("range bounds must be integers, found {}", i))
+    }
+}
+
+// Strings are iterated by character, which is done on an array of them to
+// avoid walking the string again on each step
+pub(crate) fn iterable_object(obj: Object) -> Result<Object, String> {
+    match obj {
+        Object::String(i)            => Ok(Object::Array(i.chars().map(|i| Object::String(i.to_string())).collect())),
+        i @ Object::Array(_)         => Ok(i),
+        i @ Object::Hash(_)          => Ok(i),
+        i @ Object::Range(_, _, _)   => Ok(i),
+        i                            => Err(format!("{} is not iterable", i))
+    }
+}
 
-    let args = if let Some(i) = args {
-        Some(eval_expressions(i, env)?)
-    } else {None};
+// The values bound by the `index`-th iteration: the element, or the key for
+// hashes, and with `pair` the index or key followed by the element or value
+pub(crate) fn loop_bindings(iterable: &Object, index: usize, pair: bool) -> Option<Vec<Object>> {
+    let (key, value) = match iterable {
+        Object::Array(i)       => (Object::Integer(index as i64), i.get(index)?.clone()),
+        Object::Hash(i)        => {
+            let (key, value) = i.pair(index)?;
+            if !pair {return Some(vec!(key.clone()))}
+            (key.clone(), value.clone())
+        },
+        Object::Range(i, e, a) => {
+            let value = *i as i128 + index as i128;
+            if value > *e as i128 || (value == *e as i128 && !a) {return None}
+            (Object::Integer(index as i64), Object::Integer(value as i64))
+        },
+        _                      => return None
+    };
 
-    Ok(apply_function(obj, args)?)
+    Some(if pair {vec!(key, value)} else {vec!(value)})
 }
 
-fn apply_function<'a>(obj: Object<'a>, args: Option<Vec<Object<'a>>>) -> Result<Object<'a>, String> {
-    let params;
-    let env;
-    let statements;
+fn eval_call_expression(exp: &Expression, args: &[Expression], span: Span, env: EnvRc) -> Result<Object, Error> {
+    let obj = operand!(exp, Rc::clone(&env));
 
-    if let Object::Function(i, a, b) = obj {
-        params = i;
-        statements = a;
-        env = b;
-    } else {
-        return Err(format!("Runtime error: {} is not a function", obj))
+    let big_integers = env.borrow().big_integers();
+    let args = match eval_expressions(args, env)? {
+        Ok(i)  => i,
+        Err(i) => return Ok(i)
+    };
+
+    apply_function(obj, args, span, big_integers)
+}
+
+fn apply_function(obj: Object, args: Vec<Object>, span: Span, big_integers: bool) -> Result<Object, Error> {
+    let (params, statements, env) = match obj {
+        Object::Function(i, a, b) => (i, a, b),
+        Object::Builtin(i)        => return (i.func)(args, big_integers).map_err(err_at(span)),
+        Object::Native(i)         => return (i.func)(&args).map_err(|e| e.or_span(span)),
+        _                         => return Err(Error::Runtime(format!("{} is not a function", obj), span))
     };
 
-    let extended_env = extend_function_env(params, env, args)?;
-    Ok(eval_statements_unwrap(statements, extended_env)?)
+    let extended_env = extend_function_env(&params, env, args).map_err(err_at(span))?;
+    eval_statements_unwrap(&statements, extended_env)
 }
 
-fn extend_function_env<'a>(params: Option<Vec<&'a str>>, env: EnvRc<'a>, args: Option<Vec<Object<'a>>>) -> Result<EnvRc<'a>, String> {
+fn extend_function_env(params: &[String], env: EnvRc, args: Vec<Object>) -> Result<EnvRc, String> {
     let env = environment::new_enclosed_environment(env);
-    match (params, args) {
-        (Some(i), Some(e)) => {
-            if i.len() != e.len() {
-                return Err(format!("Runtime error: Expected {} arguments, got {} parameters", i.len(), e.len()))
+
+    check_arguments(params.len(), args.len())?;
+
+    for (param, arg) in params.iter().zip(args) {
+        env.borrow_mut().set(param, arg)
+    }
+    Ok(env)
+}
+
+pub(crate) fn check_arguments(params: usize, args: usize) -> Result<(), String> {
+    if params != args {
+        Err(format!("expected {} arguments, got {}", params, args))
+    } else {
+        Ok(())
+    }
+}
+
+// The values of the expressions, or the return, break or continue that cut them short
+fn eval_expressions(args: &[Expression], env: EnvRc) -> Result<Result<Vec<Object>, Object>, Error> {
+    let mut objs = Vec::new();
+    for arg in args {
+        match eval_expression(arg, Rc::clone(&env))? {
+            i @ (Object::Return(_) | Object::Break | Object::Continue) => return Ok(Err(i)),
+            i                                                          => objs.push(i)
+        }
+    }
+
+    Ok(Ok(objs))
+}
+
+fn eval_hash_literal(pairs: &[(Expression, Expression, Span)], env: EnvRc) -> Result<Object, Error> {
+    let mut hash = HashPairs::default();
+
+    for (key, value, span) in pairs {
+        let key   = operand!(key, Rc::clone(&env));
+        let value = operand!(value, Rc::clone(&env));
+
+        hash.insert(key, value).map_err(err_at(*span))?;
+    }
+
+    Ok(Object::Hash(hash))
+}
+
+fn eval_index_expression(left: &Expression, index: &Expression, span: Span, env: EnvRc) -> Result<Object, Error> {
+    let left  = operand!(left, Rc::clone(&env));
+    let index = operand!(index, env);
+
+    index_object(left, index).map_err(err_at(span))
+}
+
+pub(crate) fn index_object(left: Object, index: Object) -> Result<Object, String> {
+    match (left, index) {
+        (Object::Array(a), Object::Integer(i)) => {
+            if i < 0 {
+                return Err(format!("negative index {}", i))
             }
-            for (i, param) in i.iter().enumerate() {
-                env.borrow_mut().set(param, e[i].clone())
+
+            let len = a.len();
+            match a.into_iter().nth(i as usize) {
+                Some(i) => Ok(i),
+                None    => Err(format!("index {} out of bounds for array of length {}", i, len))
             }
-            Ok(env)
         },
-        (None, None) => return Ok(env),
-        _            => return Err(format!("Runtime error: Function has the wrong number of parameters"))
+        (Object::Hash(h), i) => Ok(h.get(&i)?.cloned().unwrap_or(Object::Null)),
+        (Object::Array(_), i) => Err(format!("array index must be an integer, found {}", i)),
+        (i, _)                => Err(format!("{} is not indexable", i))
     }
 }
 
-fn eval_expressions<'a>(args: Vec<Expression<'a>>, env: EnvRc<'a>) -> Result<Vec<Object<'a>>, String> {
-    let mut objs = Vec::new();
-    for arg in args.iter() {
-        objs.push(eval_expression(arg.clone(), Rc::clone(&env))?)
+// Evaluates the target's indices, then the value, and returns the value assigned.
+// A compound assignment reads the variable first, like `x = x + value` would, a
+// plain one right before storing, so changes made by the value aren't lost
+fn eval_assign_expression(target: &Expression, infix: Option<&Infix>, value: &Expression, span: Span, env: EnvRc) -> Result<Object, Error> {
+    let (name, name_span, indices) = assignment_path(target);
+
+    let current = match infix {
+        Some(_) => Some(env.borrow().get(name).map_err(err_at(name_span))?),
+        None    => None
+    };
+    let mut index_values = Vec::new();
+    for index in &indices {
+        index_values.push(operand!(index, Rc::clone(&env)));
+    }
+
+    let value = operand!(value, Rc::clone(&env));
+    let big_integers = env.borrow().big_integers();
+
+    let current = match current {
+        Some(i)                    => i,
+        None if indices.is_empty() => Object::Null,
+        None                       => env.borrow().get(name).map_err(err_at(name_span))?
+    };
+
+    let (root, value) = update_value(current, &index_values, value, infix, big_integers).map_err(err_at(span))?;
+
+    env.borrow_mut().assign(name, root).map_err(err_at(span))?;
+    Ok(value)
+}
+
+// The variable an assignment target is rooted at, and the index expressions
+// applied to it, outermost first
+pub(crate) fn assignment_path(target: &Expression) -> (&str, Span, Vec<&Expression>) {
+    match target {
+        Expression::IndexExpression(i, e, _) => {
+            let (name, span, mut indices) = assignment_path(i);
+            indices.push(e);
+            (name, span, indices)
+        },
+        Expression::Ident(i, s)              => (i, *s, Vec::new()),
+        _                                    => unreachable!("checked by the parser")
     }
+}
+
+// The new value of an assignment's variable, given its `current` one: `value`
+// stored at `[indices[0]][indices[1]]...`, or combined with what is there with
+// `infix` for compound assignments. Also returns the value stored
+pub(crate) fn update_value(current: Object, indices: &[Object], value: Object, infix: Option<&Infix>, big_integers: bool) -> Result<(Object, Object), String> {
+    let (index, rest) = match indices.split_first() {
+        Some(i) => i,
+        None    => {
+            let value = match infix {
+                Some(i) => infix_objects(current, i.clone(), value, big_integers)?,
+                None    => value
+            };
+            return Ok((value.clone(), value))
+        }
+    };
+
+    let element = if rest.is_empty() && infix.is_none() {
+        Object::Null
+    } else {
+        index_object(current.clone(), index.clone())?
+    };
+    let (element, value) = update_value(element, rest, value, infix, big_integers)?;
 
-    Ok(objs)
+    Ok((set_index_object(current, index.clone(), element)?, value))
 }
 
-fn eval_prefix_expression<'a>(prefix: Prefix, exp: Expression<'a>, env: EnvRc<'a>) -> Result<Object<'a>, String> {
-    Ok(match prefix {
-        Prefix::Not         => eval_not_prefix(eval_expression(exp, env)?)?,
-        Prefix::PrefixMinus => eval_minus_prefix(eval_expression(exp, env)?)?
-    })
+fn set_index_object(container: Object, index: Object, value: Object) -> Result<Object, String> {
+    match (container, index) {
+        (Object::Array(mut a), Object::Integer(i)) => {
+            if i < 0 {
+                return Err(format!("negative index {}", i))
+            }
+
+            let len = a.len();
+            match a.get_mut(i as usize) {
+                Some(e) => *e = value,
+                None    => return Err(format!("index {} out of bounds for array of length {}", i, len))
+            }
+            Ok(Object::Array(a))
+        },
+        (Object::Hash(mut h), i)  => {
+            h.insert(i, value)?;
+            Ok(Object::Hash(h))
+        },
+        (Object::Array(_), i)     => Err(format!("array index must be an integer, found {}", i)),
+        (i, _)                    => Err(format!("{} does not support index assignment", i))
+    }
+}
+
+fn eval_prefix_expression(prefix: &Prefix, exp: &Expression, span: Span, env: EnvRc) -> Result<Object, Error> {
+    let obj = operand!(exp, Rc::clone(&env));
+    let big_integers = env.borrow().big_integers();
+
+    prefix_object(prefix.clone(), obj, big_integers).map_err(err_at(span))
 }
 
-fn eval_not_prefix<'a>(obj: Object) -> Result<Object<'a>, String> {
+// `big_integers` promotes integers that overflow to BigInt instead of failing
+pub(crate) fn prefix_object(prefix: Prefix, obj: Object, big_integers: bool) -> Result<Object, String> {
+    match prefix {
+        Prefix::Not         => eval_not_prefix(obj),
+        Prefix::PrefixMinus => eval_minus_prefix(obj, big_integers),
+        Prefix::BitNot      => eval_bit_not_prefix(obj)
+    }
+}
+
+fn eval_not_prefix(obj: Object) -> Result<Object, String> {
     if let Object::Boolean(i) = obj {
         Ok(Object::Boolean(!i))
     } else {
-        Err(format!("Runtime error: !{}", obj))
+        Err(format!("unsupported operand for !: {}", obj))
+    }
+}
+
+fn eval_minus_prefix(obj: Object, big_integers: bool) -> Result<Object, String> {
+    match obj {
+        Object::Integer(i)    => match i.checked_neg() {
+            Some(i)                => Ok(Object::Integer(i)),
+            None if big_integers   => Ok(big_integer(BigInt::from(i).neg())),
+            None                   => Err(format!("integer overflow in -({})", i))
+        },
+        Object::BigInteger(i) => Ok(big_integer(i.neg())),
+        Object::Float(i)      => Ok(Object::Float(-i)),
+        i                     => Err(format!("unsupported operand for -: {}", i))
+    }
+}
+
+// ~x is -x - 1 in two's complement, which also holds for big integers
+fn eval_bit_not_prefix(obj: Object) -> Result<Object, String> {
+    match obj {
+        Object::Integer(i)    => Ok(Object::Integer(!i)),
+        Object::BigInteger(i) => Ok(big_integer(i.neg().sub(&BigInt::from(1)))),
+        i                     => Err(format!("unsupported operand for ~: {}", i))
+    }
+}
+
+fn eval_infix_expression(left: &Expression, infix: &Infix, right: &Expression, span: Span, env: EnvRc) -> Result<Object, Error> {
+    let left  = operand!(left, Rc::clone(&env));
+
+    if let Infix::And | Infix::Or = infix {
+        return eval_logical_expression(left, infix, right, span, env)
+    }
+
+    let right = operand!(right, Rc::clone(&env));
+    let big_integers = env.borrow().big_integers();
+
+    infix_objects(left, infix.clone(), right, big_integers).map_err(err_at(span))
+}
+
+fn eval_logical_expression(left: Object, infix: &Infix, right: &Expression, span: Span, env: EnvRc) -> Result<Object, Error> {
+    let left = is_boolean(left).map_err(err_at(span))?;
+
+    // true || x and false && x are decided without looking at x
+    if left == (*infix == Infix::Or) {
+        return Ok(Object::Boolean(left))
+    }
+
+    let right = operand!(right, env);
+    Ok(Object::Boolean(is_boolean(right).map_err(err_at(span))?))
+}
+
+// `big_integers` promotes integers that overflow to BigInt instead of failing
+pub(crate) fn infix_objects(left: Object, infix: Infix, right: Object, big_integers: bool) -> Result<Object, String> {
+    if let (Object::String(l), Object::String(r)) = (&left, &right) {
+        return eval_string_infix_expression(l, infix, r)
     }
+
+    Ok(match (infix, left, right) {
+        (Infix::And, l, r)                              => Object::Boolean(is_boolean(l)? && is_boolean(r)?),
+        (Infix::Or, l, r)                               => Object::Boolean(is_boolean(l)? || is_boolean(r)?),
+        // any float operand makes the whole operation a float one
+        (infix, l, r) if is_float(&l, &r)               => eval_float_infix_expression(to_float(l)?, infix, to_float(r)?)?,
+        (Infix::Equal, l, r)                            => Object::Boolean(l == r),
+        (Infix::NotEqual, l, r)                         => Object::Boolean(l != r),
+        (infix, Object::Integer(l), Object::Integer(r)) => eval_integer_infix_expression(l, infix, r, big_integers)?,
+        (infix, l, r)                                   => eval_big_infix_expression(is_big_integer(l)?, infix, is_big_integer(r)?)?
+    })
+}
+
+fn eval_integer_infix_expression(left: i64, infix: Infix, right: i64, big_integers: bool) -> Result<Object, String> {
+    let result = match infix {
+        Infix::Plus         => left.checked_add(right),
+        Infix::Minus        => left.checked_sub(right),
+        Infix::Multiply     => left.checked_mul(right),
+        Infix::Divide       => {
+            if right == 0 {
+                return Err(String::from("division by zero"))
+            }
+            left.checked_div(right)
+        },
+        // truncates like /, so the remainder has the sign of the dividend
+        Infix::Modulo       => {
+            if right == 0 {
+                return Err(String::from("division by zero"))
+            }
+            Some(left.wrapping_rem(right))
+        },
+        Infix::Power        => {
+            if right < 0 {
+                return Err(negative_exponent(left, right))
+            }
+            checked_pow(left, right)
+        },
+        Infix::ShiftLeft    => {
+            if right < 0 {
+                return Err(negative_shift(right))
+            }
+            checked_shl(left, right)
+        },
+        Infix::ShiftRight   => {
+            if right < 0 {
+                return Err(negative_shift(right))
+            }
+            Some(left >> right.min(63))
+        },
+        Infix::BitAnd       => Some(left & right),
+        Infix::BitOr        => Some(left | right),
+        Infix::BitXor       => Some(left ^ right),
+        Infix::LessThan     => return Ok(Object::Boolean(left < right)),
+        Infix::GreaterThan  => return Ok(Object::Boolean(left > right)),
+        Infix::LessEqual    => return Ok(Object::Boolean(left <= right)),
+        Infix::GreaterEqual => return Ok(Object::Boolean(left >= right)),
+        Infix::Equal        => return Ok(Object::Boolean(left == right)),
+        Infix::NotEqual     => return Ok(Object::Boolean(left != right)),
+        Infix::And | Infix::Or => return Err(format!("unsupported operator {} for numbers", infix))
+    };
+
+    match result {
+        Some(i)              => Ok(Object::Integer(i)),
+        None if big_integers => eval_big_infix_expression(BigInt::from(left), infix, BigInt::from(right)),
+        None                 => Err(format!("integer overflow in {} {} {}", left, infix, right))
+    }
+}
+
+fn checked_pow(base: i64, exponent: i64) -> Option<i64> {
+    match (u32::try_from(exponent), base) {
+        (Ok(e), _)   => base.checked_pow(e),
+        // larger exponents only fit for the bases that don't grow
+        (Err(_), 0)  => Some(0),
+        (Err(_), 1)  => Some(1),
+        (Err(_), -1) => Some(if exponent % 2 == 0 {1} else {-1}),
+        (Err(_), _)  => None
+    }
+}
+
+fn checked_shl(value: i64, shift: i64) -> Option<i64> {
+    if shift >= 64 {
+        return if value == 0 {Some(0)} else {None}
+    }
+
+    let result = value << shift;
+    if result >> shift == value {Some(result)} else {None}
 }
 
-fn eval_minus_prefix<'a>(obj: Object) -> Result<Object<'a>, String> {
-    if let Object::Integer(i) = obj {
-        Ok(Object::Integer(-i))
+fn negative_exponent(base: impl fmt::Display, exponent: impl fmt::Display) -> String {
+    format!("negative exponent in {} ** {}", base, exponent)
+}
+
+fn negative_shift(shift: impl fmt::Display) -> String {
+    format!("negative shift amount {}", shift)
+}
+
+// Infinity and NaN have no literal to print back as, so results that would be
+// one are errors, like integer overflow
+fn eval_float_infix_expression(left: f64, infix: Infix, right: f64) -> Result<Object, String> {
+    let result = match infix {
+        Infix::Plus         => left + right,
+        Infix::Minus        => left - right,
+        Infix::Multiply     => left * right,
+        Infix::Divide       => {
+            if right == 0.0 {
+                return Err(String::from("division by zero"))
+            }
+            left / right
+        },
+        Infix::Modulo       => {
+            if right == 0.0 {
+                return Err(String::from("division by zero"))
+            }
+            left % right
+        },
+        Infix::Power        => left.powf(right),
+        Infix::LessThan     => return Ok(Object::Boolean(left < right)),
+        Infix::GreaterThan  => return Ok(Object::Boolean(left > right)),
+        Infix::LessEqual    => return Ok(Object::Boolean(left <= right)),
+        Infix::GreaterEqual => return Ok(Object::Boolean(left >= right)),
+        Infix::Equal        => return Ok(Object::Boolean(left == right)),
+        Infix::NotEqual     => return Ok(Object::Boolean(left != right)),
+        _                   => return Err(format!("unsupported operator {} for floats", infix))
+    };
+
+    if result.is_nan() {
+        Err(format!("undefined float result in {:?} {} {:?}", left, infix, right))
+    } else if result.is_infinite() {
+        Err(format!("float overflow in {:?} {} {:?}", left, infix, right))
     } else {
-        Err(format!("Runtime error: -{}", obj))
+        Ok(Object::Float(result))
     }
 }
 
-fn eval_infix_expression<'a>(left: Expression<'a>, infix: Infix, right: Expression<'a>, env: EnvRc<'a>) -> Result<Object<'a>, String> {
-    let left  = eval_expression(left, Rc::clone(&env))?;
-    let right = eval_expression(right, env)?;
+// Largest result in bits that *, ** and << may produce, since working out a
+// much larger one would take practically forever
+const MAX_BIG_BITS: f64 = (1 << 18) as f64;
+
+fn eval_big_infix_expression(left: BigInt, infix: Infix, right: BigInt) -> Result<Object, String> {
+    let zero = BigInt::from(0);
+    let too_large = || format!("integer result of {} is larger than {} bits", infix, MAX_BIG_BITS);
+
+    Ok(big_integer(match infix {
+        Infix::Plus         => left.add(&right),
+        Infix::Minus        => left.sub(&right),
+        Infix::Multiply     => {
+            if left.log2() + right.log2() > MAX_BIG_BITS {
+                return Err(too_large())
+            }
+            left.mul(&right)
+        },
+        Infix::Divide       => match left.div_rem(&right) {
+            Some((i, _)) => i,
+            None         => return Err(String::from("division by zero"))
+        },
+        Infix::Modulo       => match left.div_rem(&right) {
+            Some((_, i)) => i,
+            None         => return Err(String::from("division by zero"))
+        },
+        Infix::Power        => {
+            if right < zero {
+                return Err(negative_exponent(left, right))
+            }
+            let exponent = big_operand(&right, "exponent")?;
+
+            if left.log2() * exponent as f64 > MAX_BIG_BITS {
+                return Err(too_large())
+            }
+            left.pow(exponent)
+        },
+        Infix::ShiftLeft    => {
+            if right < zero {
+                return Err(negative_shift(right))
+            }
+            let shift = big_operand(&right, "shift amount")?;
 
+            if !left.is_zero() && left.log2() + shift as f64 > MAX_BIG_BITS {
+                return Err(too_large())
+            }
+            left.mul(&BigInt::from(2).pow(shift))
+        },
+        // an arithmetic shift rounds toward negative infinity, division truncates
+        Infix::ShiftRight   => {
+            if right < zero {
+                return Err(negative_shift(right))
+            }
+            // shifting out every bit leaves the sign
+            if right > BigInt::from(left.log2() as i64 + 1) {
+                return Ok(Object::Integer(if left < zero {-1} else {0}))
+            }
+            let divisor = BigInt::from(2).pow(big_operand(&right, "shift amount")?);
+
+            match left.div_rem(&divisor) {
+                Some((q, r)) if left < zero && !r.is_zero() => q.sub(&BigInt::from(1)),
+                Some((q, _))                                => q,
+                None                                        => zero
+            }
+        },
+        Infix::BitAnd       => left.bit_and(&right),
+        Infix::BitOr        => left.bit_or(&right),
+        Infix::BitXor       => left.bit_xor(&right),
+        Infix::LessThan     => return Ok(Object::Boolean(left < right)),
+        Infix::GreaterThan  => return Ok(Object::Boolean(left > right)),
+        Infix::LessEqual    => return Ok(Object::Boolean(left <= right)),
+        Infix::GreaterEqual => return Ok(Object::Boolean(left >= right)),
+        Infix::Equal        => return Ok(Object::Boolean(left == right)),
+        Infix::NotEqual     => return Ok(Object::Boolean(left != right)),
+        _                   => return Err(format!("unsupported operator {} for integers outside the i64 range", infix))
+    }))
+}
+
+fn big_operand(value: &BigInt, name: &str) -> Result<u32, String> {
+    value.to_i64()
+        .and_then(|i| u32::try_from(i).ok())
+        .ok_or_else(|| format!("{} {} is too large", name, value))
+}
+
+// Results that fit go back to a plain integer, so each value has a single representation
+fn big_integer(value: BigInt) -> Object {
+    match value.to_i64() {
+        Some(i) => Object::Integer(i),
+        None    => Object::BigInteger(value)
+    }
+}
+
+fn eval_string_infix_expression(left: &str, infix: Infix, right: &str) -> Result<Object, String> {
     Ok(match infix {
-        Infix::Plus        => Object::Integer(is_integer(left)? + is_integer(right)?),
-        Infix::Minus       => Object::Integer(is_integer(left)? - is_integer(right)?),
-        Infix::Multiply    => Object::Integer(is_integer(left)? * is_integer(right)?),
-        Infix::Divide      => Object::Integer(is_integer(left)? / is_integer(right)?),
-        Infix::LessThan    => Object::Boolean(is_integer(left)? < is_integer(right)?),
-        Infix::GreaterThan => Object::Boolean(is_integer(left)? > is_integer(right)?),
-        Infix::Equal       => Object::Boolean(left == right),
-        Infix::NotEqual    => Object::Boolean(left != right),
+        Infix::Plus         => Object::String(format!("{}{}", left, right)),
+        Infix::LessThan     => Object::Boolean(left < right),
+        Infix::GreaterThan  => Object::Boolean(left > right),
+        Infix::LessEqual    => Object::Boolean(left <= right),
+        Infix::GreaterEqual => Object::Boolean(left >= right),
+        Infix::Equal        => Object::Boolean(left == right),
+        Infix::NotEqual     => Object::Boolean(left != right),
+        _                   => return Err(format!("unsupported operator {} for strings", infix))
     })
 }
 
-fn eval_if_expression<'a>(condition: Expression<'a>, consequence: Vec<Statement<'a>>, alternative: Option<Vec<Statement<'a>>>, env: EnvRc<'a>) -> Result<Object<'a>, String> {
-    let condition = eval_expression(condition, Rc::clone(&env))?;
+fn eval_if_expression(condition: &Expression, consequence: &[Statement], alternative: Option<&[Statement]>, span: Span, env: EnvRc) -> Result<Object, Error> {
+    let condition = operand!(condition, Rc::clone(&env));
     
     if let Object::Boolean(c) = condition {
         Ok(if c {
@@ -162,14 +698,41 @@ fn eval_if_expression<'a>(condition: Expression<'a>, consequence: Vec<Statement<
             Object::Null
         })
     } else {
-        Err(format!("Runtime error: Expected boolean, found {}", condition))
+        Err(Error::Runtime(format!("expected boolean, found {}", condition), span))
     }
 }
 
-fn is_integer(obj: Object) -> Result<i64, String> {
-    if let Object::Integer(i) = obj {
-        Ok(i)
-    } else {
-        Err(format!("Runtime error: expected integer, found {}", obj))
+fn is_boolean(obj: Object) -> Result<bool, String> {
+    match obj {
+        Object::Boolean(i) => Ok(i),
+        i                  => Err(format!("expected boolean, found {}", i))
     }
 }
+
+fn is_big_integer(obj: Object) -> Result<BigInt, String> {
+    match obj {
+        Object::Integer(i)    => Ok(BigInt::from(i)),
+        Object::BigInteger(i) => Ok(i),
+        i                     => Err(format!("expected number, found {}", i))
+    }
+}
+
+// Either side is a float and the other one is a number
+fn is_float(left: &Object, right: &Object) -> bool {
+    let is_number = |i: &Object| matches!(i, Object::Integer(_) | Object::BigInteger(_) | Object::Float(_));
+
+    (matches!(left, Object::Float(_)) || matches!(right, Object::Float(_))) && is_number(left) && is_number(right)
+}
+
+fn to_float(obj: Object) -> Result<f64, String> {
+    match obj {
+        Object::Integer(i)    => Ok(i as f64),
+        Object::BigInteger(i) => Ok(i.to_f64()),
+        Object::Float(i)      => Ok(i),
+        i                     => Err(format!("expected number, found {}", i))
+    }
+}
+
+fn err_at(span: Span) -> impl FnOnce(String) -> Error {
+    move |err| Error::Runtime(err, span)
+}
@@ -1,26 +1,235 @@
 use std::fmt;
+use std::collections::HashMap;
+use std::rc::Rc;
 use crate::parser::Statement;
 use crate::evaluator::environment::{EnvRc};
+use crate::evaluator::builtins::{Builtin, NativeFunction};
+use crate::vm::Closure;
+use crate::evaluator::bigint::BigInt;
 
-#[derive(PartialEq, Clone)]
-pub enum Object<'a> {
+#[derive(Clone)]
+pub enum Object {
     Integer(i64),
+    // integer too large for an i64, only produced when big integers are enabled
+    BigInteger(BigInt),
+    Float(f64),
     Boolean(bool),
-    Function(Option<Vec<&'a str>>, Vec<Statement<'a>>, EnvRc<'a>),
-    Return(Box<Object<'a>>),
+    String(String),
+    Array(Vec<Object>),
+    Hash(HashPairs),
+    // start, end and whether end is included, iterated without building a list
+    Range(i64, i64, bool),
+    Function(Rc<Vec<String>>, Rc<Vec<Statement>>, EnvRc),
+    Closure(Rc<Closure>),
+    Builtin(&'static Builtin),
+    Native(Rc<NativeFunction>),
+    Return(Box<Object>),
+    // unwind the statements of a loop body, like Return does for a function
+    Break,
+    Continue,
     Null
 }
 
-impl<'a> fmt::Display for Object<'a> {
+#[derive(PartialEq, Eq, Hash, Clone)]
+pub enum HashKey {
+    Integer(i64),
+    BigInteger(BigInt),
+    Boolean(bool),
+    String(String)
+}
+
+impl From<HashKey> for Object {
+
+    fn from(key: HashKey) -> Object {
+        match key {
+            HashKey::Integer(i)    => Object::Integer(i),
+            HashKey::BigInteger(i) => Object::BigInteger(i),
+            HashKey::Boolean(i)    => Object::Boolean(i),
+            HashKey::String(i)     => Object::String(i)
+        }
+    }
+
+}
+
+// Key/value pairs in insertion order, indexed by the hashed key
+#[derive(Clone, Default)]
+pub struct HashPairs {
+    index: HashMap<HashKey, usize>,
+    pairs: Vec<(Object, Object)>
+}
+
+impl HashPairs {
+
+    pub fn insert(&mut self, key: Object, value: Object) -> Result<(), String> {
+        let hash_key = key.hash_key()?;
+
+        self.insert_hashed(hash_key, key, value);
+        Ok(())
+    }
+
+    pub fn insert_key(&mut self, key: HashKey, value: Object) {
+        self.insert_hashed(key.clone(), Object::from(key), value)
+    }
+
+    fn insert_hashed(&mut self, hash_key: HashKey, key: Object, value: Object) {
+        match self.index.get(&hash_key) {
+            Some(i) => self.pairs[*i].1 = value,
+            None    => {
+                self.index.insert(hash_key, self.pairs.len());
+                self.pairs.push((key, value));
+            }
+        }
+    }
+
+    pub fn get(&self, key: &Object) -> Result<Option<&Object>, String> {
+        Ok(self.index.get(&key.hash_key()?).map(|i| &self.pairs[*i].1))
+    }
+
+    pub fn len(&self) -> usize {
+        self.pairs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pairs.is_empty()
+    }
+
+    // The pair inserted `index`-th
+    pub fn pair(&self, index: usize) -> Option<&(Object, Object)> {
+        self.pairs.get(index)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &(Object, Object)> {
+        self.pairs.iter()
+    }
+
+}
+
+impl PartialEq for HashPairs {
+
+    fn eq(&self, other: &Self) -> bool {
+        self.len() == other.len() && self.iter().all(|(k, v)| {
+            matches!(other.get(k), Ok(Some(i)) if i == v)
+        })
+    }
+
+}
+
+// Functions are equal when they are the same function, comparing their
+// environments would go through the function again
+impl PartialEq for Object {
+
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Object::Integer(a), Object::Integer(b))               => a == b,
+            (Object::BigInteger(a), Object::BigInteger(b))         => a == b,
+            (Object::Float(a), Object::Float(b))                   => a == b,
+            (Object::Boolean(a), Object::Boolean(b))               => a == b,
+            (Object::String(a), Object::String(b))                 => a == b,
+            (Object::Array(a), Object::Array(b))                   => a == b,
+            (Object::Hash(a), Object::Hash(b))                     => a == b,
+            (Object::Range(a, b, c), Object::Range(d, e, f))       => (a, b, c) == (d, e, f),
+            (Object::Function(_, a, b), Object::Function(_, c, d)) => Rc::ptr_eq(a, c) && Rc::ptr_eq(b, d),
+            (Object::Closure(a), Object::Closure(b))               => a == b,
+            (Object::Builtin(a), Object::Builtin(b))               => a == b,
+            (Object::Native(a), Object::Native(b))                 => a == b,
+            (Object::Return(a), Object::Return(b))                 => a == b,
+            (Object::Break, Object::Break)                         => true,
+            (Object::Continue, Object::Continue)                   => true,
+            (Object::Null, Object::Null)                           => true,
+            _                                                      => false
+        }
+    }
+
+}
+
+impl Object {
+
+    pub fn hash_key(&self) -> Result<HashKey, String> {
+        Ok(match self {
+            Object::Integer(i)    => HashKey::Integer(*i),
+            Object::BigInteger(i) => HashKey::BigInteger(i.clone()),
+            Object::Boolean(i)    => HashKey::Boolean(*i),
+            Object::String(i)     => HashKey::String(i.clone()),
+            i                     => return Err(format!("unusable as hash key: {}", i))
+        })
+    }
+
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Object::Integer(_)        => "integer",
+            Object::BigInteger(_)     => "integer",
+            Object::Float(_)          => "float",
+            Object::Boolean(_)        => "boolean",
+            Object::String(_)         => "string",
+            Object::Array(_)          => "array",
+            Object::Hash(_)           => "hash",
+            Object::Range(_, _, _)    => "range",
+            Object::Function(_, _, _) => "function",
+            Object::Closure(_)        => "function",
+            Object::Builtin(_)        => "builtin",
+            Object::Native(_)         => "builtin",
+            Object::Return(i)         => i.type_name(),
+            Object::Break             => "break",
+            Object::Continue          => "continue",
+            Object::Null              => "null"
+        }
+    }
+
+    fn fmt_nested(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Object::String(i) => write!(f, "{:?}", i),
+            i                 => write!(f, "{}", i)
+        }
+    }
+
+}
+
+impl fmt::Display for Object {
 
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
             Object::Integer(i)        => write!(f, "{}", i),
+            Object::BigInteger(i)     => write!(f, "{}", i),
+            // Debug keeps the `.0` and the shortest digits that read back to the same value
+            Object::Float(i)          => write!(f, "{:?}", i),
             Object::Boolean(i)        => write!(f, "{}", i),
+            Object::String(i)         => write!(f, "{}", i),
+            Object::Array(i)          => {
+                write!(f, "[")?;
+                for (n, obj) in i.iter().enumerate() {
+                    if n != 0 {write!(f, ", ")?}
+                    obj.fmt_nested(f)?;
+                }
+                write!(f, "]")
+            },
+            Object::Hash(i)           => {
+                write!(f, "{{")?;
+                for (n, (key, value)) in i.iter().enumerate() {
+                    if n != 0 {write!(f, ", ")?}
+                    key.fmt_nested(f)?;
+                    write!(f, ": ")?;
+                    value.fmt_nested(f)?;
+                }
+                write!(f, "}}")
+            },
+            Object::Range(i, e, a)    => write!(f, "{}..{}{}", i, if *a {"="} else {""}, e),
             Object::Function(_, _, _) => write!(f, "fn"),
+            Object::Closure(_)        => write!(f, "fn"),
+            Object::Builtin(i)        => write!(f, "builtin {}", i.name),
+            Object::Native(i)         => write!(f, "builtin {}", i.name),
             Object::Return(i)         => write!(f, "{}", *i),
+            Object::Break             => write!(f, "break"),
+            Object::Continue          => write!(f, "continue"),
             Object::Null              => write!(f, "null")
         }
     }
 
-}
\ No newline at end of file
+}
+
+impl fmt::Debug for Object {
+
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.fmt_nested(f)
+    }
+
+}
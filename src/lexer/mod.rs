@@ -1,32 +1,61 @@
-#[derive(PartialEq, Clone, Copy)]
+use crate::error::{Error, Span};
+use crate::evaluator::bigint::BigInt;
+
+#[derive(PartialEq, Clone, Debug)]
+#[allow(clippy::upper_case_acronyms)]
 pub enum Token<'a> {
     //special
-    Illegal,
+    Illegal(&'static str),
     EOF,
 
     //identifier + literals
     Number(i64),
+    // integer literal too large for an i64
+    BigNumber(BigInt),
+    Float(f64),
+    Str(String),
     Identifier(&'a str),
 
     //operators
     EqualSign,
+    PlusEq,
+    MinusEq,
+    AsteriskEq,
+    SlashEq,
     PlusSign,
     MinusSign,
     BangSign,
     AsteriskSign,
     SlashSign,
+    PercentSign,
+    Power,
     LT,
     GT,
+    LTEQ,
+    GTEQ,
     EQ,
     NotEQ,
+    Ampersand,
+    Pipe,
+    Caret,
+    Tilde,
+    ShiftLeft,
+    ShiftRight,
+    DotDot,
+    DotDotEq,
+    And,
+    Or,
     
     //delimiters
     Comma,
+    Colon,
     Semicolon,
     Lparen,
     Rparen,
     Lbrace,
     Rbrace,
+    Lbracket,
+    Rbracket,
 
     //keyword
     Function,
@@ -35,88 +64,217 @@ pub enum Token<'a> {
     False,
     If,
     Else,
-    Return
+    Return,
+    While,
+    Break,
+    Continue,
+    For,
+    In
+}
+
+#[derive(PartialEq, Clone)]
+pub struct SpannedToken<'a> {
+    pub token: Token<'a>,
+    pub span:  Span,
+    // text of the `///` comments right before the token, one line each
+    pub doc:   Option<String>
+}
+
+#[derive(PartialEq, Clone, Debug)]
+pub struct LexError {
+    pub message: String,
+    pub span:    Span
 }
 
-#[derive(Clone, Copy)]
+impl From<LexError> for Error {
+
+    fn from(err: LexError) -> Error {
+        Error::Lex(err.message, err.span)
+    }
+
+}
+
+#[derive(Clone)]
 pub struct Lexer<'a> {
     code:          &'a str,
     position:      usize,
     read_position: usize,
-    ch:            u8
+    ch:            char,
+    line:          usize,
+    column:        usize,
+    // doc comment lines read since the last token
+    doc:           Vec<&'a str>,
+    // the first bad escape of the string just read, reported instead of the whole string
+    bad_escape:    Option<Span>,
+    // integer literals too large for an i64 are only valid in big integer mode
+    pub big_integers: bool,
+    // set once the EOF token has been handed out
+    finished:      bool
 }
 
-pub fn new<'a>(code: &'a str) -> Lexer {
+pub fn new(code: &str) -> Lexer<'_> {
     let mut lexer = Lexer{code, 
         position: 0, 
         read_position: 0, 
-        ch: '\0' as u8};
+        ch: '\0',
+        line: 1,
+        column: 0,
+        doc: Vec::new(),
+        bad_escape: None,
+        big_integers: false,
+        finished: false};
 
     lexer.read_char();
     lexer
 }
 
+const UNEXPECTED_CHARACTER: &str = "unexpected character";
+
+// std has no XID tables, but Alphabetic and Alphanumeric are close
+// approximations of XID_Start and XID_Continue
 fn is_letter(chr: char) -> bool {
-    'a' <= chr && chr <= 'z' || 
-    'A' <= chr && chr <= 'z' || 
+    chr.is_alphabetic() ||
+    chr == '_'
+}
+
+fn is_identifier(chr: char) -> bool {
+    chr.is_alphanumeric() ||
     chr == '_'
 }
 
 fn is_digit(chr: char) -> bool {
-    '0' <= chr && chr <= '9' 
+    chr.is_ascii_digit()
 }
 
-impl<'a> Lexer<'a> {
+// Literals that don't fit an i64 are kept whole when big integers are enabled
+fn integer_literal<'a>(digits: &str, radix: u32, big_integers: bool) -> Token<'a> {
+    match (i64::from_str_radix(digits, radix), big_integers) {
+        (Ok(i), _)     => Token::Number(i),
+        (Err(_), true) => match BigInt::parse(digits, radix) {
+            Some(i) => Token::BigNumber(i),
+            None    => Token::Illegal("invalid number literal")
+        },
+        (Err(_), _)    => Token::Illegal("integer literal too large")
+    }
+}
+
+// Tokens are produced on demand, ending with a single EOF token. Illegal
+// tokens come out as errors naming what went wrong
+impl<'a> Iterator for Lexer<'a> {
+
+    type Item = Result<SpannedToken<'a>, LexError>;
 
-    pub fn get_tokens(&mut self) -> Vec<Token<'a>> {
-        let mut tokens = vec!(self.next_token());
-        
-        while *tokens.last().unwrap() != Token::EOF {
-            tokens.push(self.next_token());
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.finished {
+            return None
         }
 
-        tokens
+        let token = self.next_token();
+        self.finished = token.token == Token::EOF;
+
+        Some(match token.token {
+            Token::Illegal(i) => Err(self.error(i, token.span)),
+            _                 => Ok(token)
+        })
     }
 
+}
+
+impl<'a> Lexer<'a> {
+
+    // Positions are byte offsets into the code, columns count characters
     fn read_char(&mut self) {
-        if self.read_position >= self.code.len() {
-            self.ch = '\0' as u8;
-        } else {
-            self.ch = self.code.as_bytes()[self.read_position];
+        if self.ch == '\n' {
+            self.line += 1;
+            self.column = 0;
         }
+
         self.position = self.read_position;
+        self.ch = self.code[self.read_position..].chars().next().unwrap_or('\0');
+        self.read_position = (self.read_position + self.ch.len_utf8()).min(self.code.len());
+        self.column += 1;
+    }
+
+    fn next_token(&mut self) -> SpannedToken<'a> {
+        let (start, line, column, token) = match self.skip_trivia() {
+            Ok(())                      => {
+                let (start, line, column) = self.location();
+                (start, line, column, self.read_token())
+            },
+            Err((start, line, column))  => (start, line, column, Token::Illegal("unterminated block comment"))
+        };
+
+        let doc = if self.doc.is_empty() {None} else {Some(self.doc.join("\n"))};
+        self.doc.clear();
+
+        let span = self.bad_escape.take()
+            .unwrap_or(Span{start, end: self.position.min(self.code.len()), line, column});
 
-        self.read_position += 1;
+        SpannedToken{token, span, doc}
     }
 
-    fn next_token(&mut self) -> Token<'a> {
-        let token: Token;
+    fn error(&self, reason: &str, span: Span) -> LexError {
+        let message = match self.code[span.start..].chars().next() {
+            Some(i) if reason == UNEXPECTED_CHARACTER => format!("{} '{}'", reason, i.escape_debug()),
+            _                                         => reason.to_string()
+        };
+
+        LexError{message: format!("{} at {}:{}", message, span.line, span.column), span}
+    }
+
+    fn location(&self) -> (usize, usize, usize) {
+        (self.position.min(self.code.len()), self.line, self.column)
+    }
 
-        self.skip_whitespace();
+    fn read_token(&mut self) -> Token<'a> {
+        let token: Token;
 
-        match self.ch as char {
+        match self.ch {
             '='  => token = if self.peek_char('=') {Token::EQ} else {Token::EqualSign},
             '!'  => token = if self.peek_char('=') {Token::NotEQ} else {Token::BangSign},
-            '+'  => token = Token::PlusSign,
-            '-'  => token = Token::MinusSign,
-            '*'  => token = Token::AsteriskSign,
-            '/'  => token = Token::SlashSign,
-            '<'  => token = Token::LT,
-            '>'  => token = Token::GT,
+            '&'  => token = if self.peek_char('&') {Token::And} else {Token::Ampersand},
+            '|'  => token = if self.peek_char('|') {Token::Or} else {Token::Pipe},
+            '^'  => token = Token::Caret,
+            '~'  => token = Token::Tilde,
+            '%'  => token = Token::PercentSign,
+            '+'  => token = if self.peek_char('=') {Token::PlusEq} else {Token::PlusSign},
+            '-'  => token = if self.peek_char('=') {Token::MinusEq} else {Token::MinusSign},
+            '*'  => token = if self.peek_char('*') {
+                Token::Power
+            } else if self.peek_char('=') {
+                Token::AsteriskEq
+            } else {
+                Token::AsteriskSign
+            },
+            '/'  => token = if self.peek_char('=') {Token::SlashEq} else {Token::SlashSign},
+            '<'  => token = if self.peek_char('=') {Token::LTEQ} else if self.peek_char('<') {Token::ShiftLeft} else {Token::LT},
+            '>'  => token = if self.peek_char('=') {Token::GTEQ} else if self.peek_char('>') {Token::ShiftRight} else {Token::GT},
             ','  => token = Token::Comma,
+            ':'  => token = Token::Colon,
             ';'  => token = Token::Semicolon,
             '('  => token = Token::Lparen,
             ')'  => token = Token::Rparen,
             '{'  => token = Token::Lbrace,
             '}'  => token = Token::Rbrace,
+            '['  => token = Token::Lbracket,
+            ']'  => token = Token::Rbracket,
+            '.'  if is_digit(self.peek_ahead(1)) => return self.read_number(),
+            '.'  => token = if !self.peek_char('.') {
+                Token::Illegal(UNEXPECTED_CHARACTER)
+            } else if self.peek_char('=') {
+                Token::DotDotEq
+            } else {
+                Token::DotDot
+            },
+            '"'  => token = self.read_string(),
             '\0' => token = Token::EOF,
             _    => {
-                    if is_letter(self.ch as char) {
+                    if is_letter(self.ch) {
                         return self.read_literal();
-                    } else if is_digit(self.ch as char) {
+                    } else if is_digit(self.ch) {
                         return self.read_number();
                     } else {
-                        token = Token::Illegal;
+                        token = Token::Illegal(UNEXPECTED_CHARACTER);
                     }
                 }
         }
@@ -125,18 +283,62 @@ impl<'a> Lexer<'a> {
         token
     }
 
-    fn skip_whitespace(&mut self) {
-        while self.ch as char == ' ' || self.ch as char == '\n' || self.ch as char == '\r' || self.ch as char == '\t' {
+    // Skips whitespace and comments, keeping the text of doc comments. An
+    // unterminated block comment gives the location where it starts
+    fn skip_trivia(&mut self) -> Result<(), (usize, usize, usize)> {
+        loop {
+            match (self.ch, self.peek_ahead(1)) {
+                (' ', _) | ('\n', _) | ('\r', _) | ('\t', _) => self.read_char(),
+                ('/', '/') => self.skip_line_comment(),
+                ('/', '*') => self.skip_block_comment()?,
+                _          => return Ok(())
+            }
+        }
+    }
+
+    // `///` starts a doc comment, but `////` is a plain one as in Rust
+    fn skip_line_comment(&mut self) {
+        let is_doc = self.peek_ahead(2) == '/' && self.peek_ahead(3) != '/';
+        let position = self.position;
+
+        while self.ch != '\n' && self.ch != '\0' {
+            self.read_char();
+        }
+
+        if is_doc {
+            let text = &self.code[position + 3..self.position];
+            self.doc.push(text.strip_prefix(' ').unwrap_or(text).trim_end_matches('\r'));
+        }
+    }
+
+    fn skip_block_comment(&mut self) -> Result<(), (usize, usize, usize)> {
+        let start = self.location();
+        let mut depth = 0;
+
+        loop {
+            match (self.ch, self.peek_ahead(1)) {
+                ('\0', _)  => return Err(start),
+                ('/', '*') => {
+                    depth += 1;
+                    self.read_char();
+                },
+                ('*', '/') => {
+                    depth -= 1;
+                    self.read_char();
+
+                    if depth == 0 {
+                        self.read_char();
+                        return Ok(())
+                    }
+                },
+                _          => ()
+            }
             self.read_char();
         }
     }
 
     fn peek_char(&mut self, chr: char) -> bool {
-        if if self.read_position >= self.code.len() {
-            '\0'
-        } else {
-            self.code.as_bytes()[self.read_position] as char
-        } == chr {
+        if self.peek_ahead(1) == chr {
             self.read_char();
             true
         } else {
@@ -146,35 +348,273 @@ impl<'a> Lexer<'a> {
 
     fn read_literal(&mut self) -> Token<'a> {
         let position = self.position;
-        while is_letter(self.ch as char) {
+        while is_identifier(self.ch) {
             self.read_char();
         }
 
         match &self.code[position..self.position] {
-            "fn"     => Token::Function,
-            "let"    => Token::Let,
-            "true"   => Token::True,
-            "false"  => Token::False,
-            "if"     => Token::If,
-            "else"   => Token::Else,
-            "return" => Token::Return,
-            _        => Token::Identifier(
-                            &self.code[position..self.position]
-                        )
+            "fn"       => Token::Function,
+            "let"      => Token::Let,
+            "true"     => Token::True,
+            "false"    => Token::False,
+            "if"       => Token::If,
+            "else"     => Token::Else,
+            "return"   => Token::Return,
+            "while"    => Token::While,
+            "break"    => Token::Break,
+            "continue" => Token::Continue,
+            "for"      => Token::For,
+            "in"       => Token::In,
+            _          => Token::Identifier(
+                              &self.code[position..self.position]
+                          )
+        }
+    }
+
+    // Character `n` places after the current one, without consuming anything
+    fn peek_ahead(&self, n: usize) -> char {
+        self.code[self.position..].chars().nth(n).unwrap_or('\0')
+    }
+
+    // Underscores may separate digits anywhere after the first one
+    fn read_digits(&mut self, radix: u32) {
+        while self.ch.is_digit(radix) || self.ch == '_' {
+            self.read_char();
         }
     }
 
     fn read_number(&mut self) -> Token<'a> {
+        if self.ch == '0' {
+            match self.peek_ahead(1) {
+                'x' => return self.read_radix_number(16),
+                'o' => return self.read_radix_number(8),
+                'b' => return self.read_radix_number(2),
+                _   => ()
+            }
+        }
+
         let position = self.position;
+        let mut float = false;
+
+        self.read_digits(10);
 
-        while is_digit(self.ch as char) {
+        // a dot needs a digit after it, so `1.` is not swallowed into a number
+        if self.ch == '.' && is_digit(self.peek_ahead(1)) {
+            float = true;
             self.read_char();
+            self.read_digits(10);
         }
 
-        Token::Number(
-            self.code[position..self.position]
-                .parse()
-                .unwrap()
-        )
+        if self.ch == 'e' || self.ch == 'E' {
+            let sign = matches!(self.peek_ahead(1), '+' | '-') as usize;
+
+            if is_digit(self.peek_ahead(1 + sign)) {
+                float = true;
+                for _ in 0..=sign {
+                    self.read_char();
+                }
+                self.read_digits(10);
+            }
+        }
+
+        // `12abc` is one bad literal, not a number and an identifier
+        if is_identifier(self.ch) {
+            while is_identifier(self.ch) {
+                self.read_char();
+            }
+            return Token::Illegal("invalid digit in number literal")
+        }
+
+        let literal = self.code[position..self.position].replace('_', "");
+
+        if float {
+            match literal.parse::<f64>() {
+                Ok(i) if i.is_finite() => Token::Float(i),
+                _                      => Token::Illegal("float literal out of range")
+            }
+        } else {
+            integer_literal(&literal, 10, self.big_integers)
+        }
+    }
+
+    // `0x`, `0o` and `0b` literals, which are always integers
+    fn read_radix_number(&mut self, radix: u32) -> Token<'a> {
+        self.read_char();
+        self.read_char();
+
+        let position = self.position;
+        self.read_digits(radix);
+        let literal = self.code[position..self.position].replace('_', "");
+
+        // `0b12` or `0xfg` is one bad literal, not a number and an identifier
+        if self.ch.is_ascii_alphanumeric() {
+            while self.ch.is_ascii_alphanumeric() {
+                self.read_char();
+            }
+            return Token::Illegal("invalid digit in number literal")
+        }
+
+        if literal.is_empty() {
+            return Token::Illegal("missing digits after number prefix")
+        }
+
+        integer_literal(&literal, radix, self.big_integers)
     }
-}
\ No newline at end of file
+
+    // A bad escape doesn't end the string, so its closing quote isn't taken
+    // for the start of another one
+    fn read_string(&mut self) -> Token<'a> {
+        let mut string = String::new();
+        let mut invalid = None;
+
+        loop {
+            self.read_char();
+
+            match self.ch {
+                '"'  => break,
+                '\0' => return Token::Illegal("unterminated string"),
+                '\\' => {
+                    let (start, line, column) = self.location();
+                    self.read_char();
+
+                    let chr = match self.ch {
+                        'n'  => Ok('\n'),
+                        't'  => Ok('\t'),
+                        'r'  => Ok('\r'),
+                        '0'  => Ok('\0'),
+                        '"'  => Ok('"'),
+                        '\\' => Ok('\\'),
+                        'u'  => self.read_unicode_escape().ok_or("invalid unicode escape"),
+                        _    => Err("invalid escape sequence")
+                    };
+
+                    match chr {
+                        Ok(i)                       => string.push(i),
+                        Err(i) if invalid.is_none() => {
+                            let end = self.read_position.min(self.code.len());
+                            invalid = Some((i, Span{start, end, line, column}));
+                        },
+                        Err(_)                      => ()
+                    }
+                },
+                i => string.push(i)
+            }
+        }
+
+        match invalid {
+            Some((reason, span)) => {
+                self.bad_escape = Some(span);
+                Token::Illegal(reason)
+            },
+            None                 => Token::Str(string)
+        }
+    }
+
+    fn read_unicode_escape(&mut self) -> Option<char> {
+        if !self.peek_char('{') {return None}
+
+        let position = self.read_position;
+        while !matches!(self.peek_ahead(1), '}' | '"' | '\0') {
+            self.read_char();
+        }
+
+        let digits = &self.code[position..self.read_position];
+        if !self.peek_char('}') || digits.is_empty() || digits.len() > 6 {return None}
+
+        char::from_u32(u32::from_str_radix(digits, 16).ok()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    // Tokens before EOF, with errors as their messages
+    fn lex(code: &str) -> Vec<Result<Token<'_>, String>> {
+        new(code)
+            .map(|i| i.map(|i| i.token).map_err(|i| i.message))
+            .filter(|i| *i != Ok(Token::EOF))
+            .collect()
+    }
+
+    #[test]
+    fn escapes() {
+        assert_eq!(lex(r#""a\n\t\r\0\"\\b""#), vec![Ok(Token::Str("a\n\t\r\0\"\\b".to_string()))]);
+        assert_eq!(lex(r#""\u{48}\u{e9}\u{1F600}""#), vec![Ok(Token::Str("Hé😀".to_string()))]);
+
+        // the error points at the escape and the string still ends at its quote
+        assert_eq!(lex(r#"let s = "ab\qc\z"; 1"#), vec![
+            Ok(Token::Let), Ok(Token::Identifier("s")), Ok(Token::EqualSign),
+            Err("invalid escape sequence at 1:12".to_string()),
+            Ok(Token::Semicolon), Ok(Token::Number(1))
+        ]);
+        assert_eq!(lex(r#""\u{110000}" "\u{zz}" "\u41""#), vec![
+            Err("invalid unicode escape at 1:2".to_string()),
+            Err("invalid unicode escape at 1:15".to_string()),
+            Err("invalid unicode escape at 1:24".to_string())
+        ]);
+        assert_eq!(lex(r#""\u{41" 1"#), vec![Err("invalid unicode escape at 1:2".to_string()), Ok(Token::Number(1))]);
+        assert_eq!(lex("\"abc\n"), vec![Err("unterminated string at 1:1".to_string())]);
+    }
+
+    #[test]
+    fn comments() {
+        assert_eq!(lex("1 /* a /* nested */ still comment */ 2"), vec![Ok(Token::Number(1)), Ok(Token::Number(2))]);
+        assert_eq!(lex("1 // line\n/**/ 2 /* a */ /* b */"), vec![Ok(Token::Number(1)), Ok(Token::Number(2))]);
+        assert_eq!(lex("1\n  /* a /* b */"), vec![
+            Ok(Token::Number(1)),
+            Err("unterminated block comment at 2:3".to_string())
+        ]);
+
+        let docs: Vec<_> = new("/// one\n///two\n//// plain\nlet").filter_map(|i| i.ok()?.doc).collect();
+        assert_eq!(docs, vec!["one\ntwo".to_string()]);
+    }
+
+    #[test]
+    fn numbers() {
+        assert_eq!(lex("0xff 0o17 0b1010 0xDEAD_beef 1_000_000"), vec![
+            Ok(Token::Number(255)), Ok(Token::Number(15)), Ok(Token::Number(10)),
+            Ok(Token::Number(0xdead_beef)), Ok(Token::Number(1_000_000))
+        ]);
+        assert_eq!(lex("1.5 .5 1e3 2.5E-1 1_0.2_5"), vec![
+            Ok(Token::Float(1.5)), Ok(Token::Float(0.5)), Ok(Token::Float(1000.0)),
+            Ok(Token::Float(0.25)), Ok(Token::Float(10.25))
+        ]);
+
+        // the dots of a range are not a decimal point
+        assert_eq!(lex("1..2"), vec![Ok(Token::Number(1)), Ok(Token::DotDot), Ok(Token::Number(2))]);
+
+        assert_eq!(lex("0b12 0xfg 0o8 12abc 0x 1"), vec![
+            Err("invalid digit in number literal at 1:1".to_string()),
+            Err("invalid digit in number literal at 1:6".to_string()),
+            Err("invalid digit in number literal at 1:11".to_string()),
+            Err("invalid digit in number literal at 1:15".to_string()),
+            Err("missing digits after number prefix at 1:21".to_string()),
+            Ok(Token::Number(1))
+        ]);
+        assert_eq!(lex("1e999"), vec![Err("float literal out of range at 1:1".to_string())]);
+    }
+
+    #[test]
+    fn big_numbers() {
+        let code = "9223372036854775807 9223372036854775808 0xffff_ffff_ffff_ffff_f";
+        assert_eq!(lex(code), vec![
+            Ok(Token::Number(i64::MAX)),
+            Err("integer literal too large at 1:21".to_string()),
+            Err("integer literal too large at 1:41".to_string())
+        ]);
+
+        let mut lexer = new(code);
+        lexer.big_integers = true;
+
+        let tokens: Vec<_> = lexer.filter_map(|i| i.ok()).map(|i| i.token).collect();
+        assert_eq!(tokens, vec![
+            Token::Number(i64::MAX),
+            Token::BigNumber(BigInt::parse("9223372036854775808", 10).unwrap()),
+            Token::BigNumber(BigInt::parse("fffffffffffffffff", 16).unwrap()),
+            Token::EOF
+        ]);
+    }
+
+}
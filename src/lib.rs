@@ -0,0 +1,285 @@
+mod error;
+mod lexer;
+mod parser;
+mod evaluator;
+mod compiler;
+mod vm;
+
+use evaluator::environment::{self, EnvRc};
+use evaluator::builtins::NativeFunction;
+use compiler::Constant;
+use compiler::symbol_table::{self, SymbolTable};
+use std::rc::Rc;
+
+pub use error::{Error, Span};
+pub use parser::Program;
+pub use evaluator::object::{Object, HashPairs, HashKey};
+pub use evaluator::bigint::BigInt;
+pub use evaluator::convert::{FromObject, IntoObject};
+
+/// How programs are executed. Both engines give the same results
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum Engine {
+    /// Walks the syntax tree directly
+    Evaluator,
+    /// Compiles to bytecode and runs it on a stack-based virtual machine
+    Vm
+}
+
+/// An interpreter session whose global bindings persist between calls to `eval`
+pub struct Interpreter {
+    engine:    Engine,
+    env:       EnvRc,
+    // state kept between runs by Engine::Vm
+    symbols:   SymbolTable,
+    constants: Vec<Constant>,
+    globals:   Vec<Option<Object>>,
+    big_integers: bool
+}
+
+impl Interpreter {
+
+    pub fn new() -> Interpreter {
+        Interpreter::with_engine(Engine::Evaluator)
+    }
+
+    pub fn with_engine(engine: Engine) -> Interpreter {
+        Interpreter{
+            engine,
+            env:       environment::new(),
+            symbols:   symbol_table::new(),
+            constants: Vec::new(),
+            globals:   Vec::new(),
+            big_integers: false
+        }
+    }
+
+    /// Opt-in number mode where integers that overflow an i64 become
+    /// arbitrary-precision integers instead of raising an error
+    pub fn set_big_integers(&mut self, enabled: bool) {
+        self.big_integers = enabled;
+        self.env.borrow_mut().big_integers = enabled;
+    }
+
+    /// Parses and runs `code`, returning the value of its last statement
+    /// or the first error found
+    pub fn eval(&mut self, code: &str) -> Result<Object, Error> {
+        let program = self.parse(code).map_err(|mut e| e.remove(0))?;
+
+        self.run(program)
+    }
+
+    /// Parses `code` without running it, reporting every syntax error at once.
+    /// Lexical errors come first, followed by the parse errors. Integer literals
+    /// too large for an i64 are lexical errors unless big integers are enabled
+    pub fn parse(&self, code: &str) -> Result<Program, Vec<Error>> {
+        let mut lexer = lexer::new(code);
+        lexer.big_integers = self.big_integers;
+
+        let mut parser = parser::new(lexer);
+
+        let (program, errors) = parser.parse_program();
+
+        if errors.is_empty() {Ok(program)} else {Err(errors)}
+    }
+
+    pub fn run(&mut self, program: Program) -> Result<Object, Error> {
+        match self.engine {
+            Engine::Evaluator => evaluator::run_program(program, Rc::clone(&self.env)),
+            Engine::Vm        => {
+                let symbols   = std::mem::take(&mut self.symbols);
+                let constants = std::mem::take(&mut self.constants);
+
+                let mut compiler = compiler::new_with_state(symbols, constants);
+                let main = compiler.compile(&program);
+
+                let (symbols, constants) = compiler.into_state();
+                self.symbols   = symbols;
+                self.constants = constants;
+
+                let mut vm = vm::new(&self.constants, &mut self.globals, self.symbols.global_names());
+                vm.big_integers = self.big_integers;
+                vm.run(main?)
+            }
+        }
+    }
+
+    pub fn set_global(&mut self, name: &str, value: Object) {
+        match self.engine {
+            Engine::Evaluator => self.env.borrow_mut().set(name, value),
+            Engine::Vm        => {
+                let index = self.symbols.define_global(name).index;
+
+                if self.globals.len() <= index {
+                    self.globals.resize(index + 1, None);
+                }
+                self.globals[index] = Some(value);
+            }
+        }
+    }
+
+    pub fn get_global(&self, name: &str) -> Option<Object> {
+        match self.engine {
+            Engine::Evaluator => self.env.borrow().vars.get(name).cloned(),
+            Engine::Vm        => {
+                let index = self.symbols.resolve_global(name)?.index;
+                self.globals.get(index).cloned().flatten()
+            }
+        }
+    }
+
+    /// Binds a Rust closure to a global name so scripts can call it like any other function
+    pub fn register_fn<F>(&mut self, name: &str, func: F)
+    where F: Fn(&[Object]) -> Result<Object, Error> + 'static {
+        let native = NativeFunction{name: name.to_string(), func: Box::new(func)};
+
+        self.set_global(name, Object::Native(Rc::new(native)));
+    }
+
+}
+
+impl Default for Interpreter {
+
+    fn default() -> Self {
+        Interpreter::new()
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    // Scripts with their result or error, which both engines must give
+    const SCRIPTS: &[(&str, &str)] = &[
+        // closures
+        ("let add = fn(a) { fn(b) { a + b } }; add(2)(3)", "5"),
+        ("let counter = fn() { let n = 0; fn() { n += 1; n } }; let c = counter(); c(); c(); c()", "3"),
+        ("let fib = fn(n) { if (n < 2) { n } else { fib(n - 1) + fib(n - 2) } }; fib(15)", "610"),
+        ("let fs = []; for (i in 0..3) { fs = push(fs, fn() { i }) } [fs[0](), fs[1](), fs[2]()]", "[0, 1, 2]"),
+        ("let x = 1; let f = fn() { x = x + 1 }; f(); f(); x", "3"),
+        ("let f = fn() { 1 }; let g = fn() { 1 }; [f == f, f == g, f != g]", "[true, false, true]"),
+        // loops with break and continue
+        ("let s = 0; let i = 0; while (i < 10) { i += 1; if (i % 2 == 0) { continue } s += i } s", "25"),
+        ("let s = 0; while (true) { s += 1; if (s == 5) { break } } s", "5"),
+        ("let r = []; for (i in 0..=4) { if (i == 3) { break } r = push(r, i) } r", "[0, 1, 2]"),
+        ("let r = []; for (k, v in {\"a\": 1, \"b\": 2}) { r = push(r, k + \":\" + type(v)) } r", "[\"a:integer\", \"b:integer\"]"),
+        ("let r = []; for (c in \"héllo\") { r = push(r, c) } r", "[\"h\", \"é\", \"l\", \"l\", \"o\"]"),
+        ("let r = []; for (i in 0..3) { r = push(r, if (i == 1) { continue } else { i }) } r", "[0, 2]"),
+        ("let x = 0; for (i in 0..5) { x = if (i == 2) { break } else { i } } x", "1"),
+        ("let r = []; for (i in 0..3) { for (j in 0..3) { if (j > i) { break } r = push(r, [i, j]) } } r", "[[0, 0], [1, 0], [1, 1], [2, 0], [2, 1], [2, 2]]"),
+        ("let f = fn() { for (i in 0..10) { if (i == 4) { return i * 10 } } }; f()", "40"),
+        ("let n = 0; for (i in 0..4) { n += 1 + if (i == 2) { continue } else { i } } n", "7"),
+        // assignment
+        ("let a = [1, [2, 3]]; a[1][0] = 9; a", "[1, [9, 3]]"),
+        ("let h = {\"k\": [1]}; h[\"k\"][0] += 4; h[\"n\"] = true; h", "{\"k\": [5], \"n\": true}"),
+        ("let a = 1; let b = 2; a = b = 7; [a, b]", "[7, 7]"),
+        ("let h = {}; let f = fn() { h[\"a\"] = 1; 2 }; h[\"b\"] = f(); h", "{\"a\": 1, \"b\": 2}"),
+        ("let a = [0, 0]; let f = fn() { a = [5, 5]; 1 }; a[0] = f(); a", "[1, 5]"),
+        ("let x = 1; let f = fn() { x = 10; 2 }; x += f(); x", "3"),
+        ("let s = \"x\"; s += \"y\"; s", "xy"),
+        ("if (false) { len = 1 } len([1])", "1"),
+        // arithmetic
+        ("[7 / 2, -7 % 3, 2 ** 10, 1 << 4, ~5, 6 & 3, 6 | 3, 6 ^ 3, 1.5 * 2, round(2.5)]", "[3, -1, 1024, 16, -6, 2, 7, 5, 3.0, 3]"),
+        ("[1 < 2 && 2 < 3, false || true, \"a\" + \"b\", 0..3, len([1, 2])]", "[true, true, \"ab\", 0..3, 2]"),
+        // errors
+        ("1 / 0", "Runtime error: division by zero"),
+        ("9223372036854775807 + 1", "Runtime error: integer overflow in 9223372036854775807 + 1"),
+        ("let a = [1]; a[5]", "Runtime error: index 5 out of bounds for array of length 1"),
+        ("undefined_name", "Runtime error: undefined_name not found"),
+        ("undeclared = 1", "Runtime error: cannot assign to undeclared variable undeclared"),
+        ("len = 1", "Runtime error: cannot assign to undeclared variable len"),
+        ("if (false) { 99999999999999999999 } else { 1 }", "Lexer error: integer literal too large at 1:14"),
+        ("let s = \"x\"; s *= 2", "Runtime error: expected number, found x"),
+        ("if (1) { 2 }", "Runtime error: expected boolean, found 1"),
+        ("let f = fn(a) { a }; f(1, 2)", "Runtime error: expected 1 arguments, got 2"),
+        ("true && 1", "Runtime error: expected boolean, found 1"),
+        ("for (i in 5) { i }", "Runtime error: 5 is not iterable"),
+        ("{[1]: 2}", "Runtime error: unusable as hash key: [1]"),
+        ("1e308 * 10", "Runtime error: float overflow in 1e308 * 10.0"),
+        ("(-8.0) ** 0.5", "Runtime error: undefined float result in -8.0 ** 0.5"),
+        ("float(\"nan\")", "Runtime error: cannot convert \"nan\" to float")
+    ];
+
+    // Run with big integers enabled
+    const BIG_SCRIPTS: &[(&str, &str)] = &[
+        ("2 ** 100", "1267650600228229401496703205376"),
+        ("9223372036854775807 + 1 - 1", "9223372036854775807"),
+        ("-(2 ** 64) >> 3", "-2305843009213693952"),
+        ("(2 ** 70) / (2 ** 69)", "2"),
+        ("10000000000000000000 * 2", "20000000000000000000"),
+        ("0x1_0000_0000_0000_0000 - 1", "18446744073709551615"),
+        ("-9223372036854775808", "-9223372036854775808"),
+        ("int(\"99999999999999999999\") + 1", "100000000000000000000"),
+        ("int(\"-99999999999999999999\")", "-99999999999999999999"),
+        ("int(\"12x\")", "Runtime error: cannot convert \"12x\" to integer"),
+        ("(2 ** 64) & 1", "0"),
+        ("(2 ** 64) | -(2 ** 70)", "-1162144876643701751808"),
+        ("-(2 ** 64) ^ 12345", "-18446744073709539271"),
+        ("~(2 ** 64) & (2 ** 65 - 1)", "18446744073709551615"),
+        ("2 ** 4000000000", "Runtime error: integer result of ** is larger than 262144 bits"),
+        ("1 << 4000000000", "Runtime error: integer result of << is larger than 262144 bits"),
+        ("[1 ** 4000000000, -(2 ** 100) >> 4000000000]", "[1, -1]")
+    ];
+
+    fn run(engine: Engine, big_integers: bool, code: &str) -> String {
+        let mut interpreter = Interpreter::with_engine(engine);
+        interpreter.set_big_integers(big_integers);
+
+        match interpreter.eval(code) {
+            Ok(i)  => i.to_string(),
+            Err(e) => e.to_string()
+        }
+    }
+
+    #[test]
+    fn engines_agree() {
+        for (code, expected) in SCRIPTS {
+            assert_eq!(run(Engine::Evaluator, false, code), *expected, "{}", code);
+            assert_eq!(run(Engine::Vm, false, code), *expected, "{}", code);
+        }
+    }
+
+    #[test]
+    fn engines_agree_with_big_integers() {
+        for (code, expected) in BIG_SCRIPTS {
+            assert_eq!(run(Engine::Evaluator, true, code), *expected, "{}", code);
+            assert_eq!(run(Engine::Vm, true, code), *expected, "{}", code);
+        }
+    }
+
+    #[test]
+    fn vm_reuses_constants() {
+        let mut interpreter = Interpreter::with_engine(Engine::Vm);
+
+        interpreter.eval("1 + 1; len(\"ab\") * 2.5").unwrap();
+        let constants = interpreter.constants.len();
+
+        for _ in 0..100 {
+            interpreter.eval("1 + 1; len(\"ab\") * 2.5").unwrap();
+        }
+        assert_eq!(interpreter.constants.len(), constants);
+    }
+
+    #[test]
+    fn failed_builtin_assignment_keeps_builtin() {
+        for engine in &[Engine::Evaluator, Engine::Vm] {
+            let mut interpreter = Interpreter::with_engine(*engine);
+
+            assert!(interpreter.eval("len = 3").is_err());
+            assert_eq!(interpreter.eval("len([1, 2])"), Ok(Object::Integer(2)));
+        }
+    }
+
+    #[test]
+    fn globals_persist_between_runs() {
+        for engine in &[Engine::Evaluator, Engine::Vm] {
+            let mut interpreter = Interpreter::with_engine(*engine);
+
+            interpreter.eval("let x = 2; let double = fn(a) { a * x };").unwrap();
+            assert_eq!(interpreter.eval("x = 5; double(3)"), Ok(Object::Integer(15)));
+        }
+    }
+
+}
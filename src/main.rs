@@ -1,22 +1,35 @@
 use std::{env::args, fs, process::exit, io::{self, Write}};
 
-mod lexer;
-mod parser;
-mod evaluator;
-
-use evaluator::environment;
+use gaggiscript::{Interpreter, Engine, Error};
 
 fn main() {
     
-    let mut args: Vec<String> = args()
-        .collect();
+    let (flags, mut args): (Vec<String>, Vec<String>) = args()
+        .partition(|i| i.starts_with("--"));
+
+    let engine = if flags.iter().any(|i| i == "--vm") {Engine::Vm} else {Engine::Evaluator};
+
+    let mut interpreter = Interpreter::with_engine(engine);
+    interpreter.set_big_integers(flags.iter().any(|i| i == "--bigint"));
+
+    // print the parsed program instead of running it
+    let ast = flags.iter().any(|i| i == "--ast");
 
     if args.len() == 2 {
 
-        interpreter(match fs::read_to_string(args.pop().unwrap()) {
+        let code = match fs::read_to_string(args.pop().unwrap()) {
             Ok(i)  => i,
             Err(e) => {eprintln!("{}", e); exit(3)},
-        });
+        };
+
+        if let Err(errors) = run(&mut interpreter, &code, ast) {
+            report(&errors, &code);
+
+            exit(match errors[0] {
+                Error::Lex(_, _) | Error::Parse(_, _) | Error::Compile(_, _) => 1,
+                Error::Runtime(_, _)                                         => 2
+            });
+        }
 
     } else {
 
@@ -27,36 +40,54 @@ fn main() {
             print!("> ");
             io::stdout().flush().unwrap();
 
-            if let Err(e) = io::stdin().read_line(&mut code) {
-                eprintln!("{}", e); exit(4) 
+            match io::stdin().read_line(&mut code) {
+                Ok(0)  => break,
+                Ok(_)  => (),
+                Err(e) => {eprintln!("{}", e); exit(4)}
             }
 
-            if code != "exit\n" {
-                interpreter(code)
-            } else {
+            if code.trim_end() == "exit" {
                 break;
             }
+
+            if let Err(errors) = run(&mut interpreter, &code, ast) {
+                report(&errors, &code);
+            }
         }
 
     }
 
 }
 
-fn interpreter(code: String) {
-    let mut lexer = lexer::new(&code);
+fn run(interpreter: &mut Interpreter, code: &str, ast: bool) -> Result<(), Vec<Error>> {
+    let program = interpreter.parse(code)?;
+
+    if ast {
+        println!("{}", program);
+        return Ok(())
+    }
 
-    let tokens = lexer.get_tokens();
+    let result = interpreter.run(program).map_err(|e| vec!(e))?;
 
-    let mut parser = parser::new(tokens);
+    println!("{}", result);
+    Ok(())
+}
 
-    let ast = parser.parse_program();
+// Lexical errors come before syntax errors, so each kind is reported as a group
+fn report(errors: &[Error], code: &str) {
+    for e in errors {
+        eprintln!("{}", e.render(code));
+    }
 
-    let env = environment::new();
+    let lexical = errors.iter().filter(|i| matches!(i, Error::Lex(_, _))).count();
+    let syntax  = errors.iter().filter(|i| matches!(i, Error::Parse(_, _))).count();
 
-    let result = evaluator::run_program(ast, env);
+    let counts: Vec<String> = [(lexical, "lexical"), (syntax, "syntax")].iter()
+        .filter(|(count, _)| *count > 0)
+        .map(|(count, kind)| format!("{} {} error{}", count, kind, if *count == 1 {""} else {"s"}))
+        .collect();
 
-    println!("{}", match result {
-        Ok(i)  => i,
-        Err(e) => {eprintln!("{}", e); exit(2);}
-    });
+    if !counts.is_empty() {
+        eprintln!("aborting due to {}", counts.join(" and "));
+    }
 }
@@ -1,21 +1,95 @@
 use std::fmt;
+use std::rc::Rc;
+use crate::error::Span;
+use crate::evaluator::bigint::BigInt;
 
 #[derive(PartialEq, Clone)]
-pub enum Expression<'a> {
-    Ident(&'a str),
+#[allow(clippy::enum_variant_names)]
+pub enum Expression {
+    Ident(String, Span),
     Int(i64),
+    // only lexed when big integers are enabled
+    BigInt(BigInt),
+    Float(f64),
+    Str(String),
     Bool(bool),
-    IfExpression(Box<Expression<'a>>, Vec<Statement<'a>>, Option<Vec<Statement<'a>>>),
-    FunctionLiteral(Option<Vec<&'a str>>, Vec<Statement<'a>>),
-    CallExpression(Box<Expression<'a>>, Option<Vec<Expression<'a>>>),
-    PrefixExpression(Prefix, Box<Expression<'a>>),
-    InfixExpression(Box<Expression<'a>>, Infix, Box<Expression<'a>>)
+    ArrayLiteral(Vec<Expression>),
+    HashLiteral(Vec<(Expression, Expression, Span)>),
+    IndexExpression(Box<Expression>, Box<Expression>, Span),
+    IfExpression(Box<Expression>, Vec<Statement>, Option<Vec<Statement>>, Span),
+    FunctionLiteral(Rc<Vec<String>>, Rc<Vec<Statement>>),
+    CallExpression(Box<Expression>, Option<Vec<Expression>>, Span),
+    PrefixExpression(Prefix, Box<Expression>, Span),
+    InfixExpression(Box<Expression>, Infix, Box<Expression>, Span),
+    // start..end, or start..=end when the flag is set. The span is the operator
+    RangeExpression(Box<Expression>, Box<Expression>, bool, Span),
+    // `target = value`, or `target op= value` with the operator. The target is
+    // an identifier or an index expression on one, the span is the operator
+    AssignExpression(Box<Expression>, Option<Infix>, Box<Expression>, Span)
+}
+
+// Canonical source for the tree: every operation is parenthesized, so the
+// output shows exactly how precedence was resolved and parses back the same
+impl fmt::Display for Expression {
+
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Expression::Ident(i, _)                         => write!(f, "{}", i),
+            Expression::Int(i)                              => write!(f, "{}", i),
+            Expression::BigInt(i)                           => write!(f, "{}", i),
+            Expression::Float(i)                            => write!(f, "{:?}", i),
+            Expression::Str(i)                              => write!(f, "{:?}", i),
+            Expression::Bool(i)                             => write!(f, "{}", i),
+            Expression::ArrayLiteral(i)                     => write!(f, "[{}]", comma_separated(i)),
+            Expression::HashLiteral(i)                      => {
+                let pairs: Vec<String> = i.iter().map(|(k, v, _)| format!("{}: {}", k, v)).collect();
+                write!(f, "{{{}}}", pairs.join(", "))
+            },
+            Expression::IndexExpression(l, i, _)            => write!(f, "({}[{}])", l, i),
+            Expression::IfExpression(c, i, a, _)            => {
+                write!(f, "if ({}) ", c)?;
+                write_block(f, i)?;
+
+                if let Some(a) = a {
+                    write!(f, " else ")?;
+                    write_block(f, a)?;
+                }
+                Ok(())
+            },
+            Expression::FunctionLiteral(p, b)               => {
+                write!(f, "fn({}) ", p.join(", "))?;
+                write_block(f, b)
+            },
+            Expression::CallExpression(i, a, _)             => write!(f, "{}({})", i, comma_separated(a.as_deref().unwrap_or_default())),
+            Expression::PrefixExpression(p, i, _)           => write!(f, "({}{})", p, i),
+            Expression::InfixExpression(l, i, r, _)         => write!(f, "({} {} {})", l, i, r),
+            Expression::RangeExpression(l, r, inclusive, _) => write!(f, "({}{}{})", l, if *inclusive {"..="} else {".."}, r),
+            Expression::AssignExpression(t, Some(i), v, _)  => write!(f, "({} {}= {})", t, i, v),
+            Expression::AssignExpression(t, None, v, _)     => write!(f, "({} = {})", t, v)
+        }
+    }
+}
+
+fn comma_separated<T: fmt::Display>(items: &[T]) -> String {
+    items.iter().map(|i| i.to_string()).collect::<Vec<_>>().join(", ")
+}
+
+fn write_block(f: &mut fmt::Formatter, block: &[Statement]) -> fmt::Result {
+    write!(f, "{{")?;
+
+    for i in block {
+        write!(f, " {}", i)?;
+    }
+
+    write!(f, " }}")
 }
 
 #[derive(PartialEq, Clone)]
+#[allow(clippy::enum_variant_names)]
 pub enum Prefix {
     PrefixMinus,
-    Not
+    Not,
+    BitNot
 }
 
 impl fmt::Display for Prefix {
@@ -23,7 +97,8 @@ impl fmt::Display for Prefix {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
             Prefix::Not         => write!(f, "!"),
-            Prefix::PrefixMinus => write!(f, "-")
+            Prefix::PrefixMinus => write!(f, "-"),
+            Prefix::BitNot      => write!(f, "~")
         }
     }
 }
@@ -37,7 +112,19 @@ pub enum Infix {
     Equal,
     NotEqual,
     GreaterThan,
-    LessThan
+    LessThan,
+    GreaterEqual,
+    LessEqual,
+    Modulo,
+    Power,
+    BitAnd,
+    BitOr,
+    BitXor,
+    ShiftLeft,
+    ShiftRight,
+    // only evaluate their right operand when the left one doesn't decide the result
+    And,
+    Or
 }
 
 
@@ -45,25 +132,85 @@ impl fmt::Display for Infix {
 
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
-            Infix::Plus        => write!(f, "+"),
-            Infix::Minus       => write!(f, "-"),
-            Infix::Multiply    => write!(f, "*"),
-            Infix::Divide      => write!(f, "/"),
-            Infix::LessThan    => write!(f, "<"),
-            Infix::GreaterThan => write!(f, ">"),
-            Infix::Equal       => write!(f, "=="),
-            Infix::NotEqual    => write!(f, "!=")
+            Infix::Plus         => write!(f, "+"),
+            Infix::Minus        => write!(f, "-"),
+            Infix::Multiply     => write!(f, "*"),
+            Infix::Divide       => write!(f, "/"),
+            Infix::LessThan     => write!(f, "<"),
+            Infix::GreaterThan  => write!(f, ">"),
+            Infix::Equal        => write!(f, "=="),
+            Infix::NotEqual     => write!(f, "!="),
+            Infix::GreaterEqual => write!(f, ">="),
+            Infix::LessEqual    => write!(f, "<="),
+            Infix::Modulo       => write!(f, "%"),
+            Infix::Power        => write!(f, "**"),
+            Infix::BitAnd       => write!(f, "&"),
+            Infix::BitOr        => write!(f, "|"),
+            Infix::BitXor       => write!(f, "^"),
+            Infix::ShiftLeft    => write!(f, "<<"),
+            Infix::ShiftRight   => write!(f, ">>"),
+            Infix::And          => write!(f, "&&"),
+            Infix::Or           => write!(f, "||")
         }
     }
 }
 
 #[derive(PartialEq, Clone)]
-pub enum Statement<'a> {
-    LetStatement(&'a str, Expression<'a>),
-    ReturnStatement(Expression<'a>),
-    ExpressionStatement(Expression<'a>)
+#[allow(clippy::enum_variant_names)]
+pub enum Statement {
+    // with the doc comment written before the `let`
+    LetStatement(String, Expression, Option<String>),
+    ReturnStatement(Expression),
+    ExpressionStatement(Expression),
+    // the span is the condition's
+    WhileStatement(Expression, Vec<Statement>, Span),
+    // one or two loop variables, the span is the iterable's
+    ForStatement(Vec<String>, Expression, Vec<Statement>, Span),
+    BreakStatement,
+    ContinueStatement
+}
+
+impl fmt::Display for Statement {
+
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Statement::LetStatement(i, e, doc)  => {
+                // doc lines need their own line, or they would comment out the rest
+                for line in doc.iter().flat_map(|i| i.lines()) {
+                    writeln!(f, "/// {}", line)?;
+                }
+                write!(f, "let {} = {};", i, e)
+            },
+            Statement::ReturnStatement(i)       => write!(f, "return {};", i),
+            Statement::ExpressionStatement(i)   => write!(f, "{};", i),
+            Statement::WhileStatement(c, b, _)  => {
+                write!(f, "while ({}) ", c)?;
+                write_block(f, b)
+            },
+            Statement::ForStatement(n, i, b, _) => {
+                write!(f, "for ({} in {}) ", n.join(", "), i)?;
+                write_block(f, b)
+            },
+            Statement::BreakStatement           => write!(f, "break;"),
+            Statement::ContinueStatement        => write!(f, "continue;")
+        }
+    }
+}
+
+pub struct Program {
+    pub statements: Vec<Statement>
 }
 
-pub struct Program<'a> {
-    pub statements: Vec<Statement<'a>>
+// One top-level statement per line
+impl fmt::Display for Program {
+
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (n, i) in self.statements.iter().enumerate() {
+            if n > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{}", i)?;
+        }
+        Ok(())
+    }
 }
\ No newline at end of file
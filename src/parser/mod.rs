@@ -1,96 +1,300 @@
 mod ast;
 pub use ast::{Program, Statement, Expression, Prefix, Infix};
-use crate::lexer::{Token};
-use std::process::exit;
+use crate::lexer::{Lexer, Token, SpannedToken};
+use crate::error::{Error, Span};
+use std::rc::Rc;
 
 #[derive(PartialEq, PartialOrd)]
 enum Precedence {
     Lowest,
+    Assign,
+    Range,
+    LogicalOr,
+    LogicalAnd,
     Equals,
     Lessgreater,
+    BitOr,
+    BitXor,
+    BitAnd,
+    Shift,
     Sum,
     Product,
     Prefix,
-    Call
+    Power,
+    Call,
+    Index
 }
 
 fn get_precedence(token: &Token) -> Precedence {
     match token {
+        Token::EqualSign    => Precedence::Assign,
+        Token::PlusEq       => Precedence::Assign,
+        Token::MinusEq      => Precedence::Assign,
+        Token::AsteriskEq   => Precedence::Assign,
+        Token::SlashEq      => Precedence::Assign,
+        Token::DotDot       => Precedence::Range,
+        Token::DotDotEq     => Precedence::Range,
+        Token::Or           => Precedence::LogicalOr,
+        Token::And          => Precedence::LogicalAnd,
         Token::EQ           => Precedence::Equals,
         Token::NotEQ        => Precedence::Equals,
         Token::LT           => Precedence::Lessgreater,
         Token::GT           => Precedence::Lessgreater,
+        Token::LTEQ         => Precedence::Lessgreater,
+        Token::GTEQ         => Precedence::Lessgreater,
+        Token::Pipe         => Precedence::BitOr,
+        Token::Caret        => Precedence::BitXor,
+        Token::Ampersand    => Precedence::BitAnd,
+        Token::ShiftLeft    => Precedence::Shift,
+        Token::ShiftRight   => Precedence::Shift,
         Token::PlusSign     => Precedence::Sum,
         Token::MinusSign    => Precedence::Sum,
         Token::SlashSign    => Precedence::Product,
         Token::AsteriskSign => Precedence::Product,
+        Token::PercentSign  => Precedence::Product,
+        Token::Power        => Precedence::Power,
         Token::Lparen       => Precedence::Call,
+        Token::Lbracket     => Precedence::Index,
         _                   => Precedence::Lowest
     }
 }
 
+fn is_assignable(exp: &Expression) -> bool {
+    match exp {
+        Expression::Ident(_, _)                => true,
+        Expression::IndexExpression(i, _, _)   => is_assignable(i),
+        _                                      => false
+    }
+}
+
+// Tokens are pulled from the lexer as needed, so only the current and
+// peek tokens are held at any time
 pub struct Parser<'a> {
-    tokens:     Vec<Token<'a>>,
-    cur_token:  usize,
-    peek_token: usize,
+    lexer:      Lexer<'a>,
+    cur_token:  SpannedToken<'a>,
+    peek_token: SpannedToken<'a>,
+    // braces stepped over so far, so synchronize knows which blocks are open
+    braces:     i32,
+    errors:     Vec<Error>,
+    lex_errors: Vec<Error>,
+    // tokens that came right after a skipped one
+    after_skip: Vec<Span>,
+    // loops around the current statement, reset inside function bodies
+    loop_depth: usize
 }
 
-pub fn new<'a>(tokens: Vec<Token<'a>>) -> Parser<'a> {
+pub fn new(lexer: Lexer<'_>) -> Parser<'_> {
+    let eof = SpannedToken{token: Token::EOF, span: Span::default(), doc: None};
+
     let mut parser = Parser{
-        tokens,
-        cur_token:  0,
-        peek_token: 0
+        lexer,
+        cur_token:  eof.clone(),
+        peek_token: eof,
+        braces:     0,
+        errors:     Vec::new(),
+        lex_errors: Vec::new(),
+        after_skip: Vec::new(),
+        loop_depth: 0
     };
 
+    parser.peek_token = parser.read_token();
     parser.next_token();
     parser
 }
 
-fn err(err: &str) -> String {
-    String::from(err)
-}
-
 impl<'a> Parser<'a> {
 
     fn next_token(&mut self) {
-        self.cur_token = self.peek_token;
-        self.peek_token += 1;
+        match self.cur() {
+            Token::Lbrace => self.braces += 1,
+            Token::Rbrace => self.braces -= 1,
+            _             => ()
+        }
+
+        // stay on the trailing EOF instead of running past the end
+        if *self.peek() == Token::EOF {
+            self.cur_token = self.peek_token.clone();
+        } else {
+            let token = self.read_token();
+            self.cur_token = std::mem::replace(&mut self.peek_token, token);
+        }
     }
 
-    pub fn parse_program(&mut self) -> Program<'a> {
+    // Lexical errors are set aside and the offending token skipped
+    fn read_token(&mut self) -> SpannedToken<'a> {
+        let mut skipped = false;
+
+        loop {
+            match self.lexer.next() {
+                Some(Ok(i))  => {
+                    if skipped {
+                        self.after_skip.push(i.span);
+                    }
+                    return i
+                },
+                Some(Err(e)) => {
+                    self.lex_errors.push(e.into());
+                    skipped = true;
+                },
+                None         => return self.peek_token.clone()
+            }
+        }
+    }
+
+    // Parses every statement it can, returning the partial program with all the
+    // errors found: the lexical ones first, then the syntax ones. A syntax error
+    // on the token after a skipped one is left out, since the skipped token caused it
+    pub fn parse_program(&mut self) -> (Program, Vec<Error>) {
         let mut program = Program{statements: vec!()};
 
-        while self.tokens[self.cur_token] != Token::EOF {
-            program.statements.push(
-                self.parse_statement()
-                    .unwrap_or_else(|err| {eprintln!("Parser error: {}", err); exit(1)})
-            );
+        while *self.cur() != Token::EOF {
+            if let Some(i) = self.parse_recovering() {
+                program.statements.push(i);
+            }
 
             self.next_token();
         }
 
-        program
+        let mut errors = std::mem::take(&mut self.errors);
+        let after_skip = std::mem::take(&mut self.after_skip);
+        errors.retain(|i| !after_skip.contains(&i.span()));
+
+        let mut lex_errors = std::mem::take(&mut self.lex_errors);
+        lex_errors.append(&mut errors);
+
+        (program, lex_errors)
     }
 
-    fn parse_statement(&mut self) -> Result<Statement<'a>, String> {
-        Ok(match self.tokens[self.cur_token] {
-            Token::Let    => self.parse_let_statement(),
-            Token::Return => self.parse_return_statement(),
-            _             => self.parse_expression_statement(),
-        }?)
+    // Parses a statement, or records its error and skips past it
+    fn parse_recovering(&mut self) -> Option<Statement> {
+        let start = self.braces;
+
+        match self.parse_statement() {
+            Ok(i)  => Some(i),
+            Err(e) => {
+                self.errors.push(e);
+                self.synchronize(start);
+                None
+            }
+        }
     }
 
-    fn parse_let_statement(&mut self) -> Result<Statement<'a>, String> {
-        
-        let name: &str; 
-        if let Token::Identifier(i) = &self.tokens[self.peek_token] {
-            name = i;
+    // Skips to the end of the broken statement so the next one can be parsed,
+    // stepping over any block the statement opened since `start`. Stops on the
+    // brace closing the block the statement is in, for that block to take
+    fn synchronize(&mut self, start: i32) {
+        let mut depth = self.braces - start;
+
+        loop {
+            match self.cur() {
+                Token::EOF                    => return,
+                Token::Lbrace                 => depth += 1,
+                Token::Rbrace if depth < 1    => return,
+                Token::Rbrace                 => depth -= 1,
+                Token::Semicolon if depth < 1 => break,
+                _                             => ()
+            }
+
+            if depth < 1 && (self.cur_token_is(Token::Rbrace) || self.peek_token_is(Token::Let) || self.peek_token_is(Token::Return) || self.peek_token_is(Token::While) || self.peek_token_is(Token::For)) {
+                break
+            }
+
+            self.next_token();
+        }
+
+        if self.peek_token_is(Token::Semicolon) {
+            self.next_token();
+        }
+    }
+
+    fn parse_statement(&mut self) -> Result<Statement, Error> {
+        match self.cur() {
+            Token::Let                     => self.parse_let_statement(),
+            Token::Return                  => self.parse_return_statement(),
+            Token::While                   => self.parse_while_statement(),
+            Token::For                     => self.parse_for_statement(),
+            Token::Break | Token::Continue => self.parse_loop_control(),
+            _                              => self.parse_expression_statement(),
+        }
+    }
+
+    fn parse_while_statement(&mut self) -> Result<Statement, Error> {
+        if !self.expect_token(Token::Lparen) {return Err(self.err("Expected left parenthesis"))}
+
+        self.next_token();
+        let span = self.cur_span();
+        let condition = self.parse_expression(Precedence::Lowest)?;
+
+        if !self.expect_token(Token::Rparen) {return Err(self.err("Expected right parenthesis"))}
+        if !self.expect_token(Token::Lbrace) {return Err(self.err("Expected left brace"))}
+
+        self.loop_depth += 1;
+        let body = self.parse_block_statement();
+        self.loop_depth -= 1;
+
+        if self.peek_token_is(Token::Semicolon) {self.next_token()}
+
+        Ok(Statement::WhileStatement(condition, body, span))
+    }
+
+    fn parse_for_statement(&mut self) -> Result<Statement, Error> {
+        if !self.expect_token(Token::Lparen) {return Err(self.err("Expected left parenthesis"))}
+
+        let mut names = Vec::new();
+        loop {
+            if let Token::Identifier(i) = self.peek() {
+                names.push(i.to_string());
+                self.next_token();
+            } else {
+                return Err(self.err("Expected identifier"));
+            }
+
+            if names.len() == 2 || !self.expect_token(Token::Comma) {break}
+        }
+
+        if !self.expect_token(Token::In) {return Err(self.err("Expected in"))}
+
+        self.next_token();
+        let span = self.cur_span();
+        let iterable = self.parse_expression(Precedence::Lowest)?;
+
+        if !self.expect_token(Token::Rparen) {return Err(self.err("Expected right parenthesis"))}
+        if !self.expect_token(Token::Lbrace) {return Err(self.err("Expected left brace"))}
+
+        self.loop_depth += 1;
+        let body = self.parse_block_statement();
+        self.loop_depth -= 1;
+
+        if self.peek_token_is(Token::Semicolon) {self.next_token()}
+
+        Ok(Statement::ForStatement(names, iterable, body, span))
+    }
+
+    fn parse_loop_control(&mut self) -> Result<Statement, Error> {
+        let statement = if self.cur_token_is(Token::Break) {Statement::BreakStatement} else {Statement::ContinueStatement};
+
+        if self.loop_depth == 0 {
+            let keyword = if statement == Statement::BreakStatement {"break"} else {"continue"};
+            return Err(Error::Parse(format!("{} outside of a loop", keyword), self.cur_span()))
+        }
+
+        if self.peek_token_is(Token::Semicolon) {self.next_token()}
+
+        Ok(statement)
+    }
+
+    fn parse_let_statement(&mut self) -> Result<Statement, Error> {
+        let doc = self.cur_token.doc.clone();
+
+        let name: String; 
+        if let Token::Identifier(i) = self.peek() {
+            name = i.to_string();
             self.next_token();
         } else {
-            return Err(err("Expected identifier"));
+            return Err(self.err("Expected identifier"));
         }
 
-        if !self.expect_token(Token::EqualSign) {return Err(err("Expected identifier"))}
+        if !self.expect_token(Token::EqualSign) {return Err(self.err("Expected equal sign"))}
 
         self.next_token();
 
@@ -98,10 +302,10 @@ impl<'a> Parser<'a> {
 
         if self.peek_token_is(Token::Semicolon) {self.next_token()}
 
-        Ok(Statement::LetStatement(name, value))
+        Ok(Statement::LetStatement(name, value, doc))
     }
 
-    fn parse_return_statement(&mut self) -> Result<Statement<'a>, String> {
+    fn parse_return_statement(&mut self) -> Result<Statement, Error> {
         self.next_token();
 
         
@@ -112,7 +316,7 @@ impl<'a> Parser<'a> {
         Ok(Statement::ReturnStatement(return_value))
     }
 
-    fn parse_expression_statement(&mut self) -> Result<Statement<'a>, String> {
+    fn parse_expression_statement(&mut self) -> Result<Statement, Error> {
 
         let expression = self.parse_expression(Precedence::Lowest)?;
 
@@ -123,23 +327,31 @@ impl<'a> Parser<'a> {
         Ok(Statement::ExpressionStatement(expression))
     }
 
-    fn parse_expression(&mut self, precedence: Precedence) -> Result<Expression<'a>, String> {
-        let mut left_exp = match &self.tokens[self.cur_token] {
-            Token::Identifier(i)       => Ok(Expression::Ident(i)),
+    fn parse_expression(&mut self, precedence: Precedence) -> Result<Expression, Error> {
+        let span = self.cur_span();
+
+        let mut left_exp = match self.cur() {
+            Token::Identifier(i)       => Ok(Expression::Ident(i.to_string(), span)),
             Token::Number(i)           => Ok(Expression::Int(*i)),
+            Token::BigNumber(i)        => Ok(Expression::BigInt(i.clone())),
+            Token::Float(i)            => Ok(Expression::Float(*i)),
+            Token::Str(i)              => Ok(Expression::Str(i.clone())),
             Token::True | Token::False => Ok(Expression::Bool(self.cur_token_is(Token::True))),
             Token::BangSign            => Ok(self.parse_prefix_expression(Prefix::Not)?),
             Token::MinusSign           => Ok(self.parse_prefix_expression(Prefix::PrefixMinus)?),
+            Token::Tilde               => Ok(self.parse_prefix_expression(Prefix::BitNot)?),
             Token::Lparen              => Ok(self.parse_grouped_expression()?),
+            Token::Lbracket            => Ok(self.parse_array_literal()?),
+            Token::Lbrace              => Ok(self.parse_hash_literal()?),
             Token::If                  => Ok(self.parse_if_expression()?),
             Token::Function            => Ok(self.parse_function_literal()?),
-            _                          => Err(err("Expected expression"))
+            _                          => Err(Error::Parse(String::from("Expected expression"), span))
         };
 
         while !self.peek_token_is(Token::Semicolon) && precedence < self.peek_precedence() {
             self.next_token();
 
-            left_exp = match self.tokens[self.cur_token] {
+            left_exp = match self.cur() {
                 Token::PlusSign       => self.parse_infix_expression(left_exp?, Infix::Plus),
                 Token::MinusSign      => self.parse_infix_expression(left_exp?, Infix::Minus),
                 Token::SlashSign      => self.parse_infix_expression(left_exp?, Infix::Divide),
@@ -148,7 +360,26 @@ impl<'a> Parser<'a> {
                 Token::NotEQ          => self.parse_infix_expression(left_exp?, Infix::NotEqual),
                 Token::LT             => self.parse_infix_expression(left_exp?, Infix::LessThan),
                 Token::GT             => self.parse_infix_expression(left_exp?, Infix::GreaterThan),
+                Token::LTEQ           => self.parse_infix_expression(left_exp?, Infix::LessEqual),
+                Token::GTEQ           => self.parse_infix_expression(left_exp?, Infix::GreaterEqual),
+                Token::PercentSign    => self.parse_infix_expression(left_exp?, Infix::Modulo),
+                Token::Power          => self.parse_infix_expression(left_exp?, Infix::Power),
+                Token::Ampersand      => self.parse_infix_expression(left_exp?, Infix::BitAnd),
+                Token::Pipe           => self.parse_infix_expression(left_exp?, Infix::BitOr),
+                Token::Caret          => self.parse_infix_expression(left_exp?, Infix::BitXor),
+                Token::ShiftLeft      => self.parse_infix_expression(left_exp?, Infix::ShiftLeft),
+                Token::ShiftRight     => self.parse_infix_expression(left_exp?, Infix::ShiftRight),
+                Token::And            => self.parse_infix_expression(left_exp?, Infix::And),
+                Token::Or             => self.parse_infix_expression(left_exp?, Infix::Or),
+                Token::EqualSign      => self.parse_assign_expression(left_exp?, None),
+                Token::PlusEq         => self.parse_assign_expression(left_exp?, Some(Infix::Plus)),
+                Token::MinusEq        => self.parse_assign_expression(left_exp?, Some(Infix::Minus)),
+                Token::AsteriskEq     => self.parse_assign_expression(left_exp?, Some(Infix::Multiply)),
+                Token::SlashEq        => self.parse_assign_expression(left_exp?, Some(Infix::Divide)),
+                Token::DotDot         => self.parse_range_expression(left_exp?, false),
+                Token::DotDotEq       => self.parse_range_expression(left_exp?, true),
                 Token::Lparen         => self.parse_call_expression(left_exp?),
+                Token::Lbracket       => self.parse_index_expression(left_exp?),
                 _                     => left_exp
             }
         }
@@ -156,107 +387,150 @@ impl<'a> Parser<'a> {
         left_exp
     }
 
-    fn parse_prefix_expression(&mut self, prefix: Prefix) -> Result<Expression<'a>, String> {
+    fn parse_prefix_expression(&mut self, prefix: Prefix) -> Result<Expression, Error> {
+        let span = self.cur_span();
 
         self.next_token();
 
         let right = self.parse_expression(Precedence::Prefix)?;
 
-        Ok(Expression::PrefixExpression(prefix, Box::new(right)))
+        Ok(Expression::PrefixExpression(prefix, Box::new(right), span))
     }
 
-    fn parse_grouped_expression(&mut self) -> Result<Expression<'a>, String> {
+    fn parse_grouped_expression(&mut self) -> Result<Expression, Error> {
         self.next_token();
 
         let exp = self.parse_expression(Precedence::Lowest);
 
-        if self.expect_token(Token::Rparen) {exp} else {Err(err("Expected right parenthesis"))}
+        if self.expect_token(Token::Rparen) {exp} else {Err(self.err("Expected right parenthesis"))}
     }
 
-    fn parse_if_expression(&mut self) -> Result<Expression<'a>, String> {
-        if !self.expect_token(Token::Lparen) {return Err(err("Expected left parenthesis"))}
+    fn parse_if_expression(&mut self) -> Result<Expression, Error> {
+        if !self.expect_token(Token::Lparen) {return Err(self.err("Expected left parenthesis"))}
         
         self.next_token();
+        let span = self.cur_span();
         let condition = self.parse_expression(Precedence::Lowest)?;
 
-        if !self.expect_token(Token::Rparen) {return Err(err("Expected right parenthesis"))} 
-        if !self.expect_token(Token::Lbrace) {return Err(err("Expected left brace"))}
+        if !self.expect_token(Token::Rparen) {return Err(self.err("Expected right parenthesis"))} 
+        if !self.expect_token(Token::Lbrace) {return Err(self.err("Expected left brace"))}
 
-        let consequence = self.parse_block_statement()?;
+        let consequence = self.parse_block_statement();
         
         let alternative = if self.peek_token_is(Token::Else) {
             self.next_token();
 
-            if !self.expect_token(Token::Lbrace) {return Err(err("Expected left brace"))}
+            if !self.expect_token(Token::Lbrace) {return Err(self.err("Expected left brace"))}
 
-            Some(self.parse_block_statement()?)
+            Some(self.parse_block_statement())
         } else {None};
 
-        Ok(Expression::IfExpression(Box::new(condition), consequence, alternative))
+        Ok(Expression::IfExpression(Box::new(condition), consequence, alternative, span))
     }
 
-    fn parse_block_statement(&mut self) -> Result<Vec<Statement<'a>>, String> {
+    // Broken statements are left out of the block, with their errors recorded
+    fn parse_block_statement(&mut self) -> Vec<Statement> {
         let mut block = Vec::new();
         
         self.next_token();
+        let depth = self.braces;
 
         while !self.cur_token_is(Token::Rbrace) && !self.cur_token_is(Token::EOF) {
-            block.push(self.parse_statement()?);
+            match self.parse_recovering() {
+                Some(i) => block.push(i),
+                // stopped on the brace closing this block
+                None if self.cur_token_is(Token::Rbrace) && self.braces == depth => break,
+                None    => ()
+            }
             self.next_token();
         };
 
-        Ok(block)
+        block
     }
 
-    fn parse_function_literal(&mut self) -> Result<Expression<'a>, String> {
+    fn parse_function_literal(&mut self) -> Result<Expression, Error> {
 
-        if !self.expect_token(Token::Lparen) {return Err(err("Expected left parenthesis"))}
+        if !self.expect_token(Token::Lparen) {return Err(self.err("Expected left parenthesis"))}
 
         let parameters = self.parse_function_parameters()?;
 
-        if !self.expect_token(Token::Lbrace) {return Err(err("Expected left brace"))}
+        if !self.expect_token(Token::Lbrace) {return Err(self.err("Expected left brace"))}
+
+        // a loop around the function doesn't make break valid in its body
+        let loop_depth = std::mem::replace(&mut self.loop_depth, 0);
+        let body = self.parse_block_statement();
+        self.loop_depth = loop_depth;
 
-        Ok(Expression::FunctionLiteral(parameters, self.parse_block_statement()?))
+        Ok(Expression::FunctionLiteral(Rc::new(parameters.unwrap_or_default()), Rc::new(body)))
     }
 
-    fn parse_function_parameters(&mut self) -> Result<Option<Vec<&'a str>>, String> {
+    fn parse_function_parameters(&mut self) -> Result<Option<Vec<String>>, Error> {
         if self.peek_token_is(Token::Rparen) {self.next_token(); Ok(None)}
         else {
             self.next_token();
             let mut identifier = Vec::new();
-            if let Token::Identifier(i) = self.tokens[self.cur_token] {
-                identifier.push(i);
+            if let Token::Identifier(i) = self.cur() {
+                identifier.push(i.to_string());
             }
 
             while self.peek_token_is(Token::Comma) {
                 self.next_token(); self.next_token();
 
-                if let Token::Identifier(i) = self.tokens[self.cur_token] {
-                    identifier.push(i);
+                if let Token::Identifier(i) = self.cur() {
+                    identifier.push(i.to_string());
                 }
             }
 
-            if !self.expect_token(Token::Rparen) {return Err(err("Expected right parenthesis"))}
+            if !self.expect_token(Token::Rparen) {return Err(self.err("Expected right parenthesis"))}
 
             Ok(Some(identifier))
         }
     }
 
-    fn parse_infix_expression(&mut self, left: Expression<'a>, infix: Infix) -> Result<Expression<'a>, String> {
-        let precedence = self.cur_precedence();
+    fn parse_infix_expression(&mut self, left: Expression, infix: Infix) -> Result<Expression, Error> {
+        // parsing the right side one level lower makes ** right-associative
+        let precedence = if infix == Infix::Power {Precedence::Prefix} else {self.cur_precedence()};
+        let span = self.cur_span();
 
         self.next_token();
 
         let right = self.parse_expression(precedence)?;
 
-        Ok(Expression::InfixExpression(Box::new(left), infix, Box::new(right)))
+        Ok(Expression::InfixExpression(Box::new(left), infix, Box::new(right), span))
+    }
+
+    fn parse_assign_expression(&mut self, target: Expression, infix: Option<Infix>) -> Result<Expression, Error> {
+        let span = self.cur_span();
+
+        if !is_assignable(&target) {
+            return Err(Error::Parse(String::from("Invalid assignment target"), span))
+        }
+
+        self.next_token();
+
+        // parsed at the lowest precedence, so `a = b = c` assigns right to left
+        let value = self.parse_expression(Precedence::Lowest)?;
+
+        Ok(Expression::AssignExpression(Box::new(target), infix, Box::new(value), span))
+    }
+
+    fn parse_range_expression(&mut self, start: Expression, inclusive: bool) -> Result<Expression, Error> {
+        let span = self.cur_span();
+
+        self.next_token();
+
+        let end = self.parse_expression(Precedence::Range)?;
+
+        Ok(Expression::RangeExpression(Box::new(start), Box::new(end), inclusive, span))
     }
 
-    fn parse_call_expression(&mut self, left: Expression<'a>) -> Result<Expression<'a>, String> {
-        Ok(Expression::CallExpression(Box::new(left), self.parse_call_arguments()?))
+    fn parse_call_expression(&mut self, left: Expression) -> Result<Expression, Error> {
+        let span = self.cur_span();
+
+        Ok(Expression::CallExpression(Box::new(left), self.parse_call_arguments()?, span))
     }
 
-    fn parse_call_arguments(&mut self) -> Result<Option<Vec<Expression<'a>>>, String> {
+    fn parse_call_arguments(&mut self) -> Result<Option<Vec<Expression>>, Error> {
         if self.peek_token_is(Token::Rparen) {self.next_token(); Ok(None)}
         else {
             self.next_token();
@@ -268,26 +542,106 @@ impl<'a> Parser<'a> {
                 args.push(self.parse_expression(Precedence::Lowest)?)
             }
 
-            if !self.expect_token(Token::Rparen) {return Err(err("expected right parenthesis"))}
+            if !self.expect_token(Token::Rparen) {return Err(self.err("Expected right parenthesis"))}
 
             Ok(Some(args))
         }
     }
 
+    fn parse_array_literal(&mut self) -> Result<Expression, Error> {
+        Ok(Expression::ArrayLiteral(self.parse_expression_list(Token::Rbracket)?))
+    }
+
+    fn parse_hash_literal(&mut self) -> Result<Expression, Error> {
+        let mut pairs = Vec::new();
+
+        while !self.peek_token_is(Token::Rbrace) {
+            self.next_token();
+            let span = self.cur_span();
+            let key = self.parse_expression(Precedence::Lowest)?;
+
+            if !self.expect_token(Token::Colon) {return Err(self.err("Expected colon"))}
+
+            self.next_token();
+            let value = self.parse_expression(Precedence::Lowest)?;
+
+            pairs.push((key, value, span));
+
+            if !self.peek_token_is(Token::Rbrace) && !self.expect_token(Token::Comma) {
+                return Err(self.err("Expected comma or right brace"))
+            }
+        }
+
+        self.next_token();
+
+        Ok(Expression::HashLiteral(pairs))
+    }
+
+    fn parse_index_expression(&mut self, left: Expression) -> Result<Expression, Error> {
+        let span = self.cur_span();
+
+        self.next_token();
+
+        let index = self.parse_expression(Precedence::Lowest)?;
+
+        if !self.expect_token(Token::Rbracket) {return Err(self.err("Expected right bracket"))}
+
+        Ok(Expression::IndexExpression(Box::new(left), Box::new(index), span))
+    }
+
+    fn parse_expression_list(&mut self, end: Token) -> Result<Vec<Expression>, Error> {
+        let mut list = Vec::new();
+
+        if self.peek_token_is(end.clone()) {
+            self.next_token();
+            return Ok(list)
+        }
+
+        self.next_token();
+        list.push(self.parse_expression(Precedence::Lowest)?);
+
+        while self.peek_token_is(Token::Comma) {
+            self.next_token(); self.next_token();
+
+            list.push(self.parse_expression(Precedence::Lowest)?)
+        }
+
+        if !self.expect_token(end) {return Err(self.err("Expected end of list"))}
+
+        Ok(list)
+    }
+
+    fn cur(&self) -> &Token<'a> {
+        &self.cur_token.token
+    }
+
+    fn peek(&self) -> &Token<'a> {
+        &self.peek_token.token
+    }
+
+    fn cur_span(&self) -> Span {
+        self.cur_token.span
+    }
+
+    // errors are reported on the token that failed to match
+    fn err(&self, err: &str) -> Error {
+        Error::Parse(String::from(err), self.peek_token.span)
+    }
+
     fn peek_precedence(&self) -> Precedence {
-        get_precedence(&self.tokens[self.peek_token])
+        get_precedence(self.peek())
     }
 
     fn cur_precedence(&self) -> Precedence {
-        get_precedence(&self.tokens[self.cur_token])
+        get_precedence(self.cur())
     }
 
     fn cur_token_is(&self, token: Token) -> bool {
-        self.tokens[self.cur_token] == token
+        *self.cur() == token
     }
 
     fn peek_token_is(&self, token: Token) -> bool {
-        self.tokens[self.peek_token] == token
+        *self.peek() == token
     }
 
     fn expect_token(&mut self, token: Token) -> bool {
@@ -298,4 +652,100 @@ impl<'a> Parser<'a> {
             false
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::lexer;
+
+    fn parse(code: &str) -> String {
+        let (program, errors) = new(lexer::new(code)).parse_program();
+
+        assert!(errors.is_empty(), "{}: {:?}", code, errors);
+        program.to_string()
+    }
+
+    #[test]
+    fn precedence() {
+        let cases = &[
+            ("-2 ** 2",      "(-(2 ** 2));"),
+            ("2 ** 3 ** 2",  "(2 ** (3 ** 2));"),
+            ("a = b = c",    "(a = (b = c));"),
+            ("0..n + 1",     "(0..(n + 1));"),
+            ("a || b && c",  "(a || (b && c));"),
+            ("x & y == z",   "((x & y) == z);")
+        ];
+
+        for (code, expected) in cases {
+            assert_eq!(parse(code), *expected, "{}", code);
+        }
+    }
+
+    // The partial program and each error with its line and column
+    fn parse_recovering(code: &str) -> (String, Vec<(String, usize, usize)>) {
+        let (program, errors) = new(lexer::new(code)).parse_program();
+        let errors = errors.iter().map(|i| (i.to_string(), i.span().line, i.span().column)).collect();
+
+        (program.to_string(), errors)
+    }
+
+    fn error(message: &str, line: usize, column: usize) -> (String, usize, usize) {
+        (message.to_string(), line, column)
+    }
+
+    #[test]
+    fn recovery_in_function_body() {
+        let code = "let f = fn(a) {\n    let x = ;\n    let y = 2 +;\n    a\n};\nf(1)";
+        let (program, errors) = parse_recovering(code);
+
+        assert_eq!(program, "let f = fn(a) { a; };\nf(1);");
+        assert_eq!(errors, vec![
+            error("Parser error: Expected expression", 2, 13),
+            error("Parser error: Expected expression", 3, 16)
+        ]);
+    }
+
+    #[test]
+    fn recovery_in_nested_blocks() {
+        let code = "while (true) { if (x) { let = 3 } else { 1 + } }\nbreak;\nlet y = [1, 2;\nz";
+        let (program, errors) = parse_recovering(code);
+
+        assert_eq!(program, "while (true) { if (x) { } else { }; }\nz;");
+        assert_eq!(errors, vec![
+            error("Parser error: Expected identifier", 1, 29),
+            error("Parser error: Expected expression", 1, 46),
+            error("Parser error: break outside of a loop", 2, 1),
+            error("Parser error: Expected end of list", 3, 14)
+        ]);
+    }
+
+    #[test]
+    fn lex_errors_come_first() {
+        let code = "let a = 1 $ 2;\nlet = 3;\nlet b = \"\\q\";\nlet c = 0b2 + 1;";
+        let (program, errors) = parse_recovering(code);
+
+        // no syntax error for the `+` that follows the bad literal
+        assert_eq!(program, "let a = 1;\n2;");
+        assert_eq!(errors, vec![
+            error("Lexer error: unexpected character '$' at 1:11", 1, 11),
+            error("Lexer error: invalid escape sequence at 3:10", 3, 10),
+            error("Lexer error: invalid digit in number literal at 4:9", 4, 9),
+            error("Parser error: Expected identifier", 2, 5)
+        ]);
+    }
+
+    #[test]
+    fn reprint() {
+        let code = "/// adds\nlet add = fn(a, b) { return a + b * 2; };\n\
+                    let h = {\"k\": [1, 2.5, true], 3: -x};\n\
+                    for (i, v in 0..=10) { if (i % 2 == 0) { continue } else { h[\"k\"][0] += v } }\n\
+                    while (!done && n < 10) { n = n << 1 | 1; break }\n\
+                    add(1, 2)[0] ** ~3";
+
+        let printed = parse(code);
+        assert_eq!(parse(&printed), printed);
+    }
+
+}
@@ -0,0 +1,402 @@
+use crate::compiler::{CompiledFunction, Constant};
+use crate::compiler::code::{Opcode, INFIXES, NO_INFIX, read_u8, read_u16, read_u32};
+use crate::evaluator::object::{Object, HashPairs};
+use crate::evaluator::{infix_objects, prefix_object, index_object, check_arguments};
+use crate::evaluator::{range_object, iterable_object, loop_bindings, update_value};
+use crate::parser::Prefix;
+use crate::error::{Error, Span};
+use std::rc::Rc;
+use std::cell::RefCell;
+
+// Deep enough for any sensible recursion, while still catching runaway ones
+const MAX_FRAMES: usize = 1 << 16;
+
+pub struct Closure {
+    pub func: Rc<CompiledFunction>,
+    pub free: Vec<Rc<RefCell<Object>>>
+}
+
+impl PartialEq for Closure {
+
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+
+}
+
+// A local starts out as a plain value and is moved into a shared cell the first
+// time a closure captures it, so later updates are seen on both sides
+enum Local {
+    Value(Object),
+    Shared(Rc<RefCell<Object>>)
+}
+
+struct Frame {
+    closure: Rc<Closure>,
+    ip:      usize,
+    locals:  Vec<Local>,
+    // height of the stack when the function was called
+    base:    usize
+}
+
+pub struct VM<'a> {
+    constants:    &'a [Constant],
+    globals:      &'a mut Vec<Option<Object>>,
+    global_names: &'a [String],
+    stack:        Vec<Object>,
+    frames:       Vec<Frame>,
+    pub big_integers: bool
+}
+
+pub fn new<'a>(constants: &'a [Constant], globals: &'a mut Vec<Option<Object>>, global_names: &'a [String]) -> VM<'a> {
+    if globals.len() < global_names.len() {
+        globals.resize(global_names.len(), None);
+    }
+
+    VM{
+        constants,
+        globals,
+        global_names,
+        stack:  Vec::new(),
+        frames: Vec::new(),
+        big_integers: false
+    }
+}
+
+impl<'a> VM<'a> {
+
+    pub fn run(&mut self, main: Rc<CompiledFunction>) -> Result<Object, Error> {
+        let closure = Rc::new(Closure{func: main, free: Vec::new()});
+        let mut locals = Vec::new();
+        locals.resize_with(closure.func.num_locals, || Local::Value(Object::Null));
+
+        self.frames.push(Frame{closure, ip: 0, locals, base: 0});
+
+        let result = self.execute();
+
+        if result.is_err() {
+            self.stack.clear();
+            self.frames.clear();
+        }
+        result
+    }
+
+    fn execute(&mut self) -> Result<Object, Error> {
+        let mut func = Rc::clone(&self.frames.last().unwrap().closure.func);
+        let mut ip = 0;
+
+        loop {
+            let start = ip;
+            let op = Opcode::from_byte(func.instructions[ip]);
+            ip += 1;
+
+            match op {
+                Opcode::Constant      => {
+                    let index = read_u32(&func.instructions, ip);
+                    ip += 4;
+
+                    match &self.constants[index] {
+                        Constant::Object(i)   => self.stack.push(i.clone()),
+                        Constant::Function(_) => unreachable!("functions are loaded by Opcode::Closure")
+                    }
+                },
+                Opcode::Pop           => {self.pop();},
+                Opcode::True          => self.stack.push(Object::Boolean(true)),
+                Opcode::False         => self.stack.push(Object::Boolean(false)),
+                Opcode::Null          => self.stack.push(Object::Null),
+                Opcode::Infix         => {
+                    let infix = &INFIXES[read_u8(&func.instructions, ip)];
+                    ip += 1;
+
+                    let right = self.pop();
+                    let left  = self.pop();
+
+                    let obj = infix_objects(left, infix.clone(), right, self.big_integers).map_err(err_at(&func, start))?;
+                    self.stack.push(obj);
+                },
+                Opcode::Minus | Opcode::Bang | Opcode::BitNot => {
+                    let prefix = match op {
+                        Opcode::Minus => Prefix::PrefixMinus,
+                        Opcode::Bang  => Prefix::Not,
+                        _             => Prefix::BitNot
+                    };
+
+                    let obj = self.pop();
+                    let obj = prefix_object(prefix, obj, self.big_integers).map_err(err_at(&func, start))?;
+                    self.stack.push(obj);
+                },
+                Opcode::Jump          => ip = read_u32(&func.instructions, ip),
+                Opcode::JumpNotTruthy => {
+                    let target = read_u32(&func.instructions, ip);
+                    ip += 4;
+
+                    match self.pop() {
+                        Object::Boolean(true)  => (),
+                        Object::Boolean(false) => ip = target,
+                        i                      => return Err(Error::Runtime(format!("expected boolean, found {}", i), func.span_at(start)))
+                    }
+                },
+                Opcode::GetGlobal     => {
+                    let index = read_u16(&func.instructions, ip);
+                    ip += 2;
+
+                    match &self.globals[index] {
+                        Some(i) => self.stack.push(i.clone()),
+                        None    => return Err(Error::Runtime(format!("{} not found", self.global_names[index]), func.span_at(start)))
+                    }
+                },
+                Opcode::SetGlobal     => {
+                    let index = read_u16(&func.instructions, ip);
+                    ip += 2;
+
+                    self.globals[index] = Some(self.pop());
+                },
+                Opcode::AssignGlobal  => {
+                    let index = read_u16(&func.instructions, ip);
+                    ip += 2;
+
+                    if self.globals[index].is_none() {
+                        return Err(Error::Runtime(format!("cannot assign to undeclared variable {}", self.global_names[index]), func.span_at(start)))
+                    }
+                    self.globals[index] = Some(self.pop());
+                },
+                Opcode::GetLocal      => {
+                    let index = read_u8(&func.instructions, ip);
+                    ip += 1;
+
+                    let obj = match &self.frames.last().unwrap().locals[index] {
+                        Local::Value(i)  => i.clone(),
+                        Local::Shared(i) => i.borrow().clone()
+                    };
+                    self.stack.push(obj);
+                },
+                Opcode::SetLocal      => {
+                    let index = read_u8(&func.instructions, ip);
+                    ip += 1;
+
+                    let obj = self.pop();
+                    match &mut self.frames.last_mut().unwrap().locals[index] {
+                        Local::Value(i)  => *i = obj,
+                        Local::Shared(i) => *i.borrow_mut() = obj
+                    }
+                },
+                Opcode::DefineLocal   => {
+                    let index = read_u8(&func.instructions, ip);
+                    ip += 1;
+
+                    self.frames.last_mut().unwrap().locals[index] = Local::Value(self.pop());
+                },
+                Opcode::GetFree       => {
+                    let index = read_u8(&func.instructions, ip);
+                    ip += 1;
+
+                    let obj = self.frames.last().unwrap().closure.free[index].borrow().clone();
+                    self.stack.push(obj);
+                },
+                Opcode::SetFree       => {
+                    let index = read_u8(&func.instructions, ip);
+                    ip += 1;
+
+                    let obj = self.pop();
+                    *self.frames.last().unwrap().closure.free[index].borrow_mut() = obj;
+                },
+                // the variable's current value, the indices and the value are on the
+                // stack, with the current value on top for a plain assignment
+                Opcode::Update        => {
+                    let depth    = read_u8(&func.instructions, ip);
+                    let operator = read_u8(&func.instructions, ip + 1);
+                    ip += 2;
+
+                    let infix = if operator == NO_INFIX {None} else {Some(&INFIXES[operator])};
+
+                    let after = if infix.is_none() {Some(self.pop())} else {None};
+                    let value   = self.pop();
+                    let indices = self.stack.split_off(self.stack.len() - depth);
+                    let current = match after {
+                        Some(i) => i,
+                        None    => self.pop()
+                    };
+
+                    let (root, value) = update_value(current, &indices, value, infix, self.big_integers)
+                        .map_err(err_at(&func, start))?;
+
+                    self.stack.push(value);
+                    self.stack.push(root);
+                },
+                Opcode::Array         => {
+                    let len = read_u16(&func.instructions, ip);
+                    ip += 2;
+
+                    let elements = self.stack.split_off(self.stack.len() - len);
+                    self.stack.push(Object::Array(elements));
+                },
+                Opcode::Range         => {
+                    let inclusive = read_u8(&func.instructions, ip) == 1;
+                    ip += 1;
+
+                    let last  = self.pop();
+                    let first = self.pop();
+
+                    let obj = range_object(first, last, inclusive).map_err(err_at(&func, start))?;
+                    self.stack.push(obj);
+                },
+                Opcode::Iter          => {
+                    let obj = self.pop();
+                    let obj = iterable_object(obj).map_err(err_at(&func, start))?;
+
+                    self.stack.push(obj);
+                    self.stack.push(Object::Integer(0));
+                },
+                // the iterable and the next index are the top two values of the stack
+                Opcode::IterNext      => {
+                    let pair   = read_u8(&func.instructions, ip) == 1;
+                    let target = read_u32(&func.instructions, ip + 1);
+                    ip += 5;
+
+                    let len = self.stack.len();
+                    let index = match self.stack[len - 1] {
+                        Object::Integer(i) => i as usize,
+                        _                  => unreachable!("IterNext without Iter")
+                    };
+
+                    match loop_bindings(&self.stack[len - 2], index, pair) {
+                        Some(values) => {
+                            self.stack[len - 1] = Object::Integer(index as i64 + 1);
+                            self.stack.extend(values);
+                        },
+                        None         => ip = target
+                    }
+                },
+                // the message is a string constant
+                Opcode::Raise         => {
+                    let index = read_u32(&func.instructions, ip);
+
+                    let message = match &self.constants[index] {
+                        Constant::Object(i)   => i.to_string(),
+                        Constant::Function(_) => unreachable!("raise with a function constant")
+                    };
+                    return Err(Error::Runtime(message, func.span_at(start)))
+                },
+                Opcode::Hash          => self.stack.push(Object::Hash(HashPairs::default())),
+                Opcode::HashInsert    => {
+                    let value = self.pop();
+                    let key   = self.pop();
+
+                    if let Some(Object::Hash(i)) = self.stack.last_mut() {
+                        i.insert(key, value).map_err(err_at(&func, start))?;
+                    }
+                },
+                Opcode::Index         => {
+                    let index = self.pop();
+                    let left  = self.pop();
+
+                    let obj = index_object(left, index).map_err(err_at(&func, start))?;
+                    self.stack.push(obj);
+                },
+                Opcode::Call          => {
+                    let num_args = read_u8(&func.instructions, ip);
+                    ip += 1;
+
+                    let args   = self.stack.split_off(self.stack.len() - num_args);
+                    let callee = self.pop();
+
+                    match callee {
+                        Object::Closure(closure) => {
+                            check_arguments(closure.func.num_params, args.len())
+                                .map_err(err_at(&func, start))?;
+
+                            if self.frames.len() >= MAX_FRAMES {
+                                return Err(Error::Runtime(String::from("stack overflow"), func.span_at(start)))
+                            }
+
+                            let mut locals: Vec<Local> = args.into_iter().map(Local::Value).collect();
+                            locals.resize_with(closure.func.num_locals, || Local::Value(Object::Null));
+
+                            self.frames.last_mut().unwrap().ip = ip;
+                            func = Rc::clone(&closure.func);
+                            ip = 0;
+
+                            self.frames.push(Frame{closure, ip, locals, base: self.stack.len()});
+                        },
+                        Object::Builtin(i) => {
+                            let obj = (i.func)(args, self.big_integers).map_err(err_at(&func, start))?;
+                            self.stack.push(obj);
+                        },
+                        Object::Native(i)  => {
+                            let obj = (i.func)(&args).map_err(|e| e.or_span(func.span_at(start)))?;
+                            self.stack.push(obj);
+                        },
+                        i                  => return Err(Error::Runtime(format!("{} is not a function", i), func.span_at(start)))
+                    }
+                },
+                Opcode::ReturnValue   => {
+                    let value = self.pop();
+                    let frame = self.frames.pop().unwrap();
+
+                    match self.frames.last() {
+                        Some(i) => {
+                            self.stack.truncate(frame.base);
+                            self.stack.push(value);
+
+                            func = Rc::clone(&i.closure.func);
+                            ip = i.ip;
+                        },
+                        None    => {
+                            self.stack.clear();
+                            return Ok(value)
+                        }
+                    }
+                },
+                Opcode::Closure       => {
+                    let index    = read_u32(&func.instructions, ip);
+                    let num_free = read_u8(&func.instructions, ip + 4);
+                    ip += 5;
+
+                    let mut free = Vec::with_capacity(num_free);
+                    for _ in 0..num_free {
+                        let is_local = func.instructions[ip] == 1;
+                        let index    = func.instructions[ip + 1] as usize;
+                        ip += 2;
+
+                        free.push(self.capture(is_local, index));
+                    }
+
+                    let function = match &self.constants[index] {
+                        Constant::Function(i) => Rc::clone(i),
+                        Constant::Object(_)   => unreachable!("closure over a non-function constant")
+                    };
+                    self.stack.push(Object::Closure(Rc::new(Closure{func: function, free})));
+                }
+            }
+        }
+    }
+
+    fn capture(&mut self, is_local: bool, index: usize) -> Rc<RefCell<Object>> {
+        let frame = self.frames.last_mut().unwrap();
+
+        if !is_local {
+            return Rc::clone(&frame.closure.free[index])
+        }
+
+        let local = &mut frame.locals[index];
+        if let Local::Value(i) = local {
+            let cell = Rc::new(RefCell::new(std::mem::replace(i, Object::Null)));
+            *local = Local::Shared(cell);
+        }
+
+        match local {
+            Local::Shared(i) => Rc::clone(i),
+            Local::Value(_)  => unreachable!()
+        }
+    }
+
+    fn pop(&mut self) -> Object {
+        self.stack.pop().unwrap_or(Object::Null)
+    }
+
+}
+
+fn err_at(func: &CompiledFunction, ip: usize) -> impl FnOnce(String) -> Error {
+    let span: Span = func.span_at(ip);
+    move |err| Error::Runtime(err, span)
+}